@@ -0,0 +1,410 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use image_converter::converter::{self, atlas, compare, filters, formats::{self, ImageFormat}, responsive, thumbnails};
+use image_converter::converter::atlas::AtlasSpec;
+use image_converter::converter::main_converter;
+use image_converter::converter::options::{ConversionOptions, Filter};
+use image_converter::converter::presets::FilterPresets;
+use image_converter::converter::responsive::ResponsiveSpec;
+use image_converter::converter::thumbnails::ThumbnailSpec;
+
+const USAGE: &str = "\
+Usage:
+  image_converter                          start the interactive browser
+  image_converter convert --to <format> [--quality <1-100>] [--preset <name>]
+                  [--filters <list>] [--out <dir>] <files...>
+                                           convert each file, running the preset's
+                                           filters and then --filters first
+  image_converter preset list | save <name> <filters> | delete <name>
+                                           keep named filter chains, such as
+                                           \"contrast 20, sharpen 1 5\"
+  image_converter inspect [--json] <file>  print dimensions, format and metadata
+  image_converter compare [--json] [--diff <heatmap.png>] <source> <converted>
+                                           print PSNR, SSIM and size savings
+  image_converter responsive [--widths 320,640,1280,1920] [--formats webp,jpeg]
+                  [--sizes <sizes>] [--quality <1-100>] [--preset <name>]
+                  [--filters <list>] [--out <dir>] <file>
+                                           write each width in each format, plus a
+                                           <picture> snippet and a JSON manifest
+  image_converter thumbnails [--size 160x160] [--columns 6] [--format jpeg]
+                  [--no-captions] [--preset <name>] [--filters <list>]
+                  [--out <dir>] <directory>
+                                           thumbnail every image in the directory
+                                           and lay them out on a contact sheet
+  image_converter atlas [--padding 2] [--no-trim] [--pot] [--rotate] [--max-size 4096]
+                  [--out atlas.png] <images or directory...>
+                                           pack images into one PNG or WebP atlas with
+                                           a TexturePacker JSON descriptor
+  image_converter slice [--grid <WxH>] [--out <dir>] <descriptor.json | sheet>
+                                           cut an atlas, or a grid of equal frames,
+                                           back into separate images";
+
+/// Command line arguments split into positionals, `--flag`s and `--option value` pairs
+struct Args {
+    positional: Vec<String>,
+    flags: Vec<String>,
+    options: HashMap<String, String>,
+}
+
+impl Args {
+    /// `valued` lists the options that take a value
+    fn parse(args: &[String], valued: &[&str]) -> Result<Self, Box<dyn Error>> {
+        let mut parsed = Args {
+            positional: Vec::new(),
+            flags: Vec::new(),
+            options: HashMap::new(),
+        };
+        let mut iter = args.iter();
+        while let Some(arg) = iter.next() {
+            match arg.strip_prefix("--") {
+                Some(name) if valued.contains(&name) => {
+                    let value = iter.next().ok_or_else(|| format!("--{} needs a value", name))?;
+                    parsed.options.insert(name.to_string(), value.clone());
+                }
+                Some(name) => parsed.flags.push(name.to_string()),
+                None => parsed.positional.push(arg.clone()),
+            }
+        }
+        Ok(parsed)
+    }
+
+    fn flag(&self, name: &str) -> bool {
+        self.flags.iter().any(|flag| flag == name)
+    }
+
+    fn option(&self, name: &str) -> Option<&str> {
+        self.options.get(name).map(String::as_str)
+    }
+}
+
+/// Run a non-interactive command given on the command line
+pub fn run(args: &[String]) -> Result<(), Box<dyn Error>> {
+    match args[0].as_str() {
+        "convert" => convert(Args::parse(&args[1..], &["to", "quality", "preset", "filters", "out"])?),
+        "preset" => preset(Args::parse(&args[1..], &[])?),
+        "inspect" => inspect(Args::parse(&args[1..], &[])?),
+        "compare" => compare(Args::parse(&args[1..], &["diff"])?),
+        "responsive" => responsive(Args::parse(
+            &args[1..],
+            &["widths", "formats", "sizes", "quality", "preset", "filters", "out"],
+        )?),
+        "thumbnails" => thumbnails(Args::parse(
+            &args[1..],
+            &["size", "columns", "format", "preset", "filters", "out"],
+        )?),
+        "atlas" => pack_atlas(Args::parse(&args[1..], &["padding", "max-size", "out"])?),
+        "slice" => slice(Args::parse(&args[1..], &["grid", "out"])?),
+        "-h" | "--help" | "help" => {
+            println!("{}", USAGE);
+            Ok(())
+        }
+        other => Err(format!("unknown command \"{}\"\n{}", other, USAGE).into()),
+    }
+}
+
+fn convert(args: Args) -> Result<(), Box<dyn Error>> {
+    let (Some(name), false) = (args.option("to"), args.positional.is_empty()) else {
+        return Err(USAGE.into());
+    };
+    let target = format_named(name)?;
+    let options = ConversionOptions {
+        quality: quality(&args)?,
+        filters: filter_chain(&args)?,
+        output_dir: args.option("out").map(PathBuf::from),
+        ..ConversionOptions::default()
+    };
+
+    // Every file is tried; the command fails if any of them did
+    let mut failed = 0;
+    for input in &args.positional {
+        match main_converter::convert_with_options(Path::new(input), &target, &options) {
+            Ok(report) if report.skipped => println!("{} skipped", report.output_path.display()),
+            Ok(report) => println!(
+                "{} → {} ({} → {} bytes)",
+                input,
+                report.output_path.display(),
+                report.input_bytes,
+                report.output_bytes
+            ),
+            Err(e) => {
+                eprintln!("{}", e);
+                failed += 1;
+            }
+        }
+    }
+    if failed > 0 {
+        return Err(format!("{} of {} files could not be converted", failed, args.positional.len()).into());
+    }
+    Ok(())
+}
+
+fn preset(args: Args) -> Result<(), Box<dyn Error>> {
+    let path = FilterPresets::default_path().ok_or("no config directory to keep presets in")?;
+    let mut presets = FilterPresets::load(&path)?;
+    match args.positional.iter().map(String::as_str).collect::<Vec<_>>().as_slice() {
+        ["list"] => {
+            for (name, chain) in presets.iter() {
+                println!("{:<20}{}", name, filters::describe(chain));
+            }
+        }
+        ["save", name, chain] => {
+            presets.insert(name, filters::parse(chain)?);
+            presets.save(&path)?;
+        }
+        ["delete", name] => {
+            if !presets.remove(name) {
+                return Err(format!("no preset named \"{}\"", name).into());
+            }
+            presets.save(&path)?;
+        }
+        _ => return Err(USAGE.into()),
+    }
+    Ok(())
+}
+
+fn inspect(args: Args) -> Result<(), Box<dyn Error>> {
+    if args.positional.is_empty() {
+        return Err(USAGE.into());
+    }
+
+    let mut infos = Vec::new();
+    for path in &args.positional {
+        infos.push(converter::inspect(Path::new(path))?);
+    }
+
+    if args.flag("json") {
+        // A single file prints as an object, several as an array
+        let output = match infos.as_slice() {
+            [info] => serde_json::to_string_pretty(info)?,
+            _ => serde_json::to_string_pretty(&infos)?,
+        };
+        println!("{}", output);
+    } else {
+        for info in &infos {
+            println!("{}", info.path.display());
+            for (label, value) in info.fields() {
+                println!("  {:<24}{}", label, value);
+            }
+        }
+    }
+    Ok(())
+}
+
+fn compare(args: Args) -> Result<(), Box<dyn Error>> {
+    let [source, converted] = args.positional.as_slice() else {
+        return Err(USAGE.into());
+    };
+    let (source, converted) = (Path::new(source), Path::new(converted));
+
+    let comparison = compare::compare(source, converted)?;
+
+    if let Some(diff_path) = args.option("diff") {
+        let heatmap = compare::difference_map(&image::open(source)?, &image::open(converted)?)?;
+        heatmap.save(diff_path)?;
+    }
+
+    if args.flag("json") {
+        println!("{}", serde_json::to_string_pretty(&comparison)?);
+    } else {
+        println!("PSNR     {:.2} dB", comparison.psnr);
+        println!("SSIM     {:.4}", comparison.ssim);
+        println!(
+            "Size     {} → {} bytes ({:.1}% saved)",
+            comparison.reference_bytes,
+            comparison.distorted_bytes,
+            comparison.savings() * 100.0
+        );
+    }
+    Ok(())
+}
+
+fn responsive(args: Args) -> Result<(), Box<dyn Error>> {
+    let [input] = args.positional.as_slice() else {
+        return Err(USAGE.into());
+    };
+
+    let mut spec = ResponsiveSpec::default();
+    if let Some(widths) = args.option("widths") {
+        spec.widths = widths
+            .split(',')
+            .map(|width| width.trim().parse::<u32>().map_err(|_| format!("bad width \"{}\"", width)))
+            .collect::<Result<_, _>>()?;
+    }
+    if let Some(formats) = args.option("formats") {
+        spec.formats = formats
+            .split(',')
+            .map(format_named)
+            .collect::<Result<_, _>>()?;
+    }
+    if let Some(sizes) = args.option("sizes") {
+        spec.sizes = sizes.to_string();
+    }
+    let options = ConversionOptions {
+        quality: quality(&args)?,
+        filters: filter_chain(&args)?,
+        output_dir: args.option("out").map(PathBuf::from),
+        ..ConversionOptions::default()
+    };
+
+    let set = responsive::generate(Path::new(input), &spec, &options)?;
+    for variant in &set.variants {
+        println!(
+            "{:<40}{:>6} x {:<6}{:>10} bytes",
+            variant.path.display(),
+            variant.width,
+            variant.height,
+            variant.bytes
+        );
+    }
+    println!("Manifest {}", set.manifest_path.display());
+    println!("Snippet  {}\n", set.html_path.display());
+    print!("{}", set.html);
+    Ok(())
+}
+
+fn thumbnails(args: Args) -> Result<(), Box<dyn Error>> {
+    let [dir] = args.positional.as_slice() else {
+        return Err(USAGE.into());
+    };
+
+    let mut spec = ThumbnailSpec::default();
+    if let Some(size) = args.option("size") {
+        spec.size = parse_size(size)?;
+    }
+    if let Some(columns) = args.option("columns") {
+        spec.columns = columns
+            .parse::<u32>()
+            .ok()
+            .filter(|&columns| columns > 0)
+            .ok_or("--columns must be a positive number")?;
+    }
+    if let Some(name) = args.option("format") {
+        spec.format = format_named(name)?;
+    }
+    spec.captions = !args.flag("no-captions");
+    let options = ConversionOptions {
+        filters: filter_chain(&args)?,
+        output_dir: args.option("out").map(PathBuf::from),
+        ..ConversionOptions::default()
+    };
+
+    let report = thumbnails::generate(Path::new(dir), &spec, &options)?;
+    for thumbnail in &report.thumbnails {
+        println!("{}", thumbnail.display());
+    }
+    for (input, e) in &report.failed {
+        eprintln!("{}: {}", input.display(), e);
+    }
+    println!("Contact sheet {}", report.sheet_path.display());
+    Ok(())
+}
+
+fn pack_atlas(args: Args) -> Result<(), Box<dyn Error>> {
+    if args.positional.is_empty() {
+        return Err(USAGE.into());
+    }
+
+    // A directory stands for the images directly inside it
+    let mut inputs = Vec::new();
+    for path in args.positional.iter().map(PathBuf::from) {
+        if path.is_dir() {
+            inputs.extend(
+                formats::read_image_dir(&path)?
+                    .into_iter()
+                    .map(|entry| entry.path())
+                    .filter(|path| path.is_file()),
+            );
+        } else {
+            inputs.push(path);
+        }
+    }
+    let mut spec = AtlasSpec {
+        trim: !args.flag("no-trim"),
+        power_of_two: args.flag("pot"),
+        rotation: args.flag("rotate"),
+        ..AtlasSpec::default()
+    };
+    if let Some(padding) = args.option("padding") {
+        spec.padding = padding.parse().map_err(|_| "--padding must be a number")?;
+    }
+    if let Some(max_size) = args.option("max-size") {
+        spec.max_size = max_size.parse().map_err(|_| "--max-size must be a number")?;
+    }
+    let atlas_path = PathBuf::from(args.option("out").unwrap_or("atlas.png"));
+
+    let atlas = atlas::pack(&inputs, &atlas_path, &spec, &ConversionOptions::default())?;
+    let size = &atlas.descriptor.meta.size;
+    println!(
+        "{} frames in {} ({} x {})",
+        atlas.descriptor.frames.len(),
+        atlas.image_path.display(),
+        size.w,
+        size.h
+    );
+    println!("Descriptor {}", atlas.descriptor_path.display());
+    Ok(())
+}
+
+fn slice(args: Args) -> Result<(), Box<dyn Error>> {
+    let [input] = args.positional.as_slice() else {
+        return Err(USAGE.into());
+    };
+    let input = Path::new(input);
+    // Frames go to a directory named after the input unless --out says otherwise
+    let output_dir = match args.option("out") {
+        Some(dir) => PathBuf::from(dir),
+        None => input.with_extension(""),
+    };
+
+    let options = ConversionOptions::default();
+    let frames = match args.option("grid") {
+        Some(size) => atlas::slice_grid(input, parse_size(size)?, &output_dir, &options)?,
+        None => atlas::unpack(input, &output_dir, &options)?,
+    };
+    for frame in &frames {
+        println!("{}", frame.display());
+    }
+    Ok(())
+}
+
+/// `--quality`, from 1 to 100
+fn quality(args: &Args) -> Result<Option<u8>, Box<dyn Error>> {
+    let Some(quality) = args.option("quality") else {
+        return Ok(None);
+    };
+    let quality = quality
+        .parse::<u8>()
+        .ok()
+        .filter(|quality| (1..=100).contains(quality))
+        .ok_or("--quality must be a number from 1 to 100")?;
+    Ok(Some(quality))
+}
+
+/// The filters of `--preset`, followed by those of `--filters`
+fn filter_chain(args: &Args) -> Result<Vec<Filter>, Box<dyn Error>> {
+    let mut chain = Vec::new();
+    if let Some(name) = args.option("preset") {
+        let path = FilterPresets::default_path().ok_or("no config directory to keep presets in")?;
+        let presets = FilterPresets::load(&path)?;
+        chain.extend_from_slice(presets.get(name).ok_or_else(|| format!("no preset named \"{}\"", name))?);
+    }
+    if let Some(list) = args.option("filters") {
+        chain.extend(filters::parse(list)?);
+    }
+    Ok(chain)
+}
+
+/// A format from its name or extension, e.g. `jpeg` or `jpg`
+fn format_named(name: &str) -> Result<ImageFormat, String> {
+    ImageFormat::from_extension(Some(&format!(".{}", name.trim())))
+        .ok_or_else(|| format!("unknown format \"{}\"", name))
+}
+
+/// `WIDTHxHEIGHT`, both above zero
+fn parse_size(size: &str) -> Result<(u32, u32), String> {
+    size.split_once('x')
+        .and_then(|(width, height)| Some((width.trim().parse().ok()?, height.trim().parse().ok()?)))
+        .filter(|&(width, height)| width > 0 && height > 0)
+        .ok_or_else(|| format!("bad size \"{}\", expected WIDTHxHEIGHT", size))
+}
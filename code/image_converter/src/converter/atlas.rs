@@ -0,0 +1,389 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+use image::{imageops, DynamicImage, RgbaImage};
+use serde::{Deserialize, Serialize};
+use crate::converter::errors::{ConverterError, Stage};
+use crate::converter::formats::ImageFormat;
+use crate::converter::options::ConversionOptions;
+use crate::converter::{output, pipeline};
+
+/// How `pack` lays out an atlas
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AtlasSpec {
+    /// Transparent pixels around every frame and along the atlas edges
+    pub padding: u32,
+    /// Crop fully transparent borders off each image; the descriptor records the offset
+    pub trim: bool,
+    /// Round both sides of the atlas up to a power of two
+    pub power_of_two: bool,
+    /// Turn images taller than they are wide 90° clockwise so the rows pack tighter
+    pub rotation: bool,
+    /// Largest width or height the atlas may have
+    pub max_size: u32,
+}
+
+impl Default for AtlasSpec {
+    fn default() -> Self {
+        AtlasSpec {
+            padding: 2,
+            trim: true,
+            power_of_two: false,
+            rotation: false,
+            max_size: 4096,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Rect {
+    pub x: u32,
+    pub y: u32,
+    pub w: u32,
+    pub h: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Size {
+    pub w: u32,
+    pub h: u32,
+}
+
+/// Where one image sits in the atlas, in TexturePacker's terms
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Frame {
+    /// Position in the atlas. Width and height are the unrotated ones, so a rotated
+    /// frame covers `h` by `w` pixels.
+    pub frame: Rect,
+    pub rotated: bool,
+    pub trimmed: bool,
+    /// The trimmed frame's place in the original image
+    pub sprite_source_size: Rect,
+    /// Size of the original image
+    pub source_size: Size,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Meta {
+    pub app: String,
+    pub version: String,
+    /// File name of the atlas image, relative to the descriptor
+    pub image: String,
+    pub format: String,
+    pub size: Size,
+    pub scale: String,
+}
+
+/// TexturePacker "JSON (Hash)" descriptor, keyed by the file name of each image
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Descriptor {
+    pub frames: BTreeMap<String, Frame>,
+    pub meta: Meta,
+}
+
+/// Result of `pack`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Atlas {
+    pub image_path: PathBuf,
+    /// `image_path` with a `.json` extension
+    pub descriptor_path: PathBuf,
+    pub descriptor: Descriptor,
+}
+
+/// An input image ready to be placed, trimmed and rotated as the spec asks
+struct Sprite {
+    name: String,
+    pixels: RgbaImage,
+    rotated: bool,
+    /// Trimmed area in the original image
+    source_rect: Rect,
+    source_size: Size,
+}
+
+/// Pack `inputs` into one atlas at `atlas_path`, a PNG or WebP chosen by its extension,
+/// and write the descriptor next to it. Frames are named after the input file names,
+/// which therefore have to be unique.
+pub fn pack(
+    inputs: &[PathBuf],
+    atlas_path: &Path,
+    spec: &AtlasSpec,
+    options: &ConversionOptions,
+) -> Result<Atlas, ConverterError> {
+    let format = match ImageFormat::from_extension(atlas_path.to_str()) {
+        Some(format @ (ImageFormat::PNG | ImageFormat::WEBP)) => format,
+        _ => {
+            return Err(ConverterError::UnsupportedFormat(format!(
+                "An atlas is written as PNG or WebP, not {}",
+                atlas_path.display()
+            )));
+        }
+    };
+    if inputs.is_empty() {
+        return Err(ConverterError::failed(Stage::Read, "No images to pack"));
+    }
+
+    let mut sprites = Vec::new();
+    for input in inputs {
+        let name = input.file_name().unwrap_or_default().to_string_lossy().to_string();
+        if sprites.iter().any(|sprite: &Sprite| sprite.name == name) {
+            return Err(ConverterError::failed(Stage::Read, format!("Two images are named {}", name)).in_file(input));
+        }
+        let bytes = fs::read(input).map_err(|e| ConverterError::read(input, e))?;
+        let img = pipeline::decode(&bytes, &options.limits).map_err(|e| e.in_file(input))?;
+        sprites.push(sprite(name, img.to_rgba8(), spec));
+    }
+
+    let sizes: Vec<(u32, u32)> = sprites.iter().map(|sprite| sprite.pixels.dimensions()).collect();
+    let Layout { width, height, positions } = layout(&sizes, spec).ok_or_else(|| {
+        ConverterError::failed(
+            Stage::Transform,
+            format!("The images don't fit in a {0}x{0} atlas", spec.max_size),
+        )
+    })?;
+
+    let mut atlas = RgbaImage::new(width, height);
+    let mut frames = BTreeMap::new();
+    for (sprite, (x, y)) in sprites.into_iter().zip(positions) {
+        imageops::replace(&mut atlas, &sprite.pixels, x as i64, y as i64);
+        let frame = Frame {
+            frame: Rect { x, y, w: sprite.source_rect.w, h: sprite.source_rect.h },
+            rotated: sprite.rotated,
+            trimmed: (sprite.source_rect.w, sprite.source_rect.h) != (sprite.source_size.w, sprite.source_size.h),
+            sprite_source_size: sprite.source_rect,
+            source_size: sprite.source_size,
+        };
+        frames.insert(sprite.name, frame);
+    }
+
+    let descriptor = Descriptor {
+        frames,
+        meta: Meta {
+            app: env!("CARGO_PKG_NAME").to_string(),
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            image: atlas_path.file_name().unwrap_or_default().to_string_lossy().to_string(),
+            format: "RGBA8888".to_string(),
+            size: Size { w: width, h: height },
+            scale: "1".to_string(),
+        },
+    };
+    let descriptor_path = atlas_path.with_extension("json");
+    if let Some(parent) = atlas_path.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+        fs::create_dir_all(parent).map_err(|e| ConverterError::write(parent, e))?;
+    }
+    let bytes = pipeline::encode(&DynamicImage::ImageRgba8(atlas), &format, options)?;
+    output::write_atomic(atlas_path, &bytes)?;
+    let json = serde_json::to_string_pretty(&descriptor)
+        .map_err(|e| ConverterError::failed(Stage::Write, e.to_string()))?;
+    output::write_atomic(&descriptor_path, json.as_bytes())?;
+
+    Ok(Atlas { image_path: atlas_path.to_path_buf(), descriptor_path, descriptor })
+}
+
+fn sprite(name: String, img: RgbaImage, spec: &AtlasSpec) -> Sprite {
+    let source_size = Size { w: img.width(), h: img.height() };
+    let source_rect = if spec.trim {
+        opaque_bounds(&img)
+    } else {
+        Rect { x: 0, y: 0, w: img.width(), h: img.height() }
+    };
+    let mut pixels = imageops::crop_imm(&img, source_rect.x, source_rect.y, source_rect.w, source_rect.h).to_image();
+    let rotated = spec.rotation && pixels.height() > pixels.width();
+    if rotated {
+        pixels = imageops::rotate90(&pixels);
+    }
+    Sprite { name, pixels, rotated, source_rect, source_size }
+}
+
+/// Smallest rectangle holding every pixel that isn't fully transparent.
+/// A blank image keeps a single pixel, so its frame still has a size.
+fn opaque_bounds(img: &RgbaImage) -> Rect {
+    let (mut left, mut top, mut right, mut bottom) = (u32::MAX, u32::MAX, 0, 0);
+    for (x, y, pixel) in img.enumerate_pixels() {
+        if pixel[3] != 0 {
+            left = left.min(x);
+            top = top.min(y);
+            right = right.max(x);
+            bottom = bottom.max(y);
+        }
+    }
+    match left {
+        u32::MAX => Rect { x: 0, y: 0, w: 1, h: 1 },
+        _ => Rect { x: left, y: top, w: right - left + 1, h: bottom - top + 1 },
+    }
+}
+
+/// Atlas size and the top-left corner of each image, in input order
+struct Layout {
+    width: u32,
+    height: u32,
+    positions: Vec<(u32, u32)>,
+}
+
+/// Place `sizes`, trying every candidate width and keeping the smallest area
+fn layout(sizes: &[(u32, u32)], spec: &AtlasSpec) -> Option<Layout> {
+    let padding = spec.padding;
+    let widest = sizes.iter().map(|&(width, _)| width).max()? + 2 * padding;
+    let area: u64 = sizes
+        .iter()
+        .map(|&(width, height)| (width + padding) as u64 * (height + padding) as u64)
+        .sum();
+    let mut width = widest.max((area as f64).sqrt().ceil() as u32);
+    if spec.power_of_two {
+        width = width.next_power_of_two();
+    }
+    let step = (width / 8).max(1);
+
+    let mut best: Option<Layout> = None;
+    while width <= spec.max_size {
+        let (positions, mut height) = shelf_pack(sizes, width, padding);
+        if spec.power_of_two {
+            height = height.next_power_of_two();
+        }
+        let smaller = best
+            .as_ref()
+            .is_none_or(|best| (width as u64 * height as u64) < (best.width as u64 * best.height as u64));
+        if height <= spec.max_size && smaller {
+            best = Some(Layout { width, height, positions });
+        }
+        let next = if spec.power_of_two { width.checked_mul(2) } else { width.checked_add(step) };
+        let Some(next) = next else {
+            break;
+        };
+        width = next;
+    }
+    best
+}
+
+/// Rows of images, tallest first, each row as high as its first image.
+/// Returns the positions in the order of `sizes` and the height used.
+fn shelf_pack(sizes: &[(u32, u32)], width: u32, padding: u32) -> (Vec<(u32, u32)>, u32) {
+    let mut order: Vec<usize> = (0..sizes.len()).collect();
+    order.sort_by_key(|&index| std::cmp::Reverse((sizes[index].1, sizes[index].0)));
+
+    let mut positions = vec![(0, 0); sizes.len()];
+    let (mut x, mut y, mut row_height) = (padding, padding, 0);
+    for index in order {
+        let (w, h) = sizes[index];
+        if x + w + padding > width && x > padding {
+            x = padding;
+            y += row_height + padding;
+            row_height = 0;
+        }
+        positions[index] = (x, y);
+        x += w + padding;
+        row_height = row_height.max(h);
+    }
+    (positions, y + row_height + padding)
+}
+
+/// Cut every frame of the atlas described by `descriptor_path` back out, restored to its
+/// original size and orientation, into `output_dir`. Frames are written in the format
+/// their name's extension gives, or as PNG with `.png` appended.
+pub fn unpack(
+    descriptor_path: &Path,
+    output_dir: &Path,
+    options: &ConversionOptions,
+) -> Result<Vec<PathBuf>, ConverterError> {
+    let json = fs::read(descriptor_path).map_err(|e| ConverterError::read(descriptor_path, e))?;
+    let descriptor: Descriptor = serde_json::from_slice(&json)
+        .map_err(|e| ConverterError::failed(Stage::Read, e.to_string()).in_file(descriptor_path))?;
+    if !is_relative_inside(Path::new(&descriptor.meta.image)) {
+        return Err(ConverterError::failed(
+            Stage::Read,
+            format!("Atlas image {} lies outside the descriptor's directory", descriptor.meta.image),
+        )
+        .in_file(descriptor_path));
+    }
+    let image_path = descriptor_path.with_file_name(&descriptor.meta.image);
+    let bytes = fs::read(&image_path).map_err(|e| ConverterError::read(&image_path, e))?;
+    let atlas = pipeline::decode(&bytes, &options.limits)
+        .map_err(|e| e.in_file(&image_path))?
+        .to_rgba8();
+
+    let mut written = Vec::new();
+    for (name, frame) in &descriptor.frames {
+        let Rect { x, y, w, h } = frame.frame;
+        let (w, h) = if frame.rotated { (h, w) } else { (w, h) };
+        if x.saturating_add(w) > atlas.width() || y.saturating_add(h) > atlas.height() {
+            return Err(ConverterError::failed(Stage::Transform, format!("Frame {} lies outside the atlas", name))
+                .in_file(descriptor_path));
+        }
+        let mut pixels = imageops::crop_imm(&atlas, x, y, w, h).to_image();
+        if frame.rotated {
+            pixels = imageops::rotate270(&pixels);
+        }
+        let mut img = RgbaImage::new(frame.source_size.w, frame.source_size.h);
+        imageops::replace(
+            &mut img,
+            &pixels,
+            frame.sprite_source_size.x as i64,
+            frame.sprite_source_size.y as i64,
+        );
+        written.push(write_frame(&DynamicImage::ImageRgba8(img), output_dir, name, options)?);
+    }
+    Ok(written)
+}
+
+/// Cut a sheet of equally sized frames into `output_dir` as `<stem>_<n>.png`, numbered
+/// row by row from 0. Fully transparent cells, such as the unused end of the last row,
+/// are left out.
+pub fn slice_grid(
+    sheet_path: &Path,
+    frame_size: (u32, u32),
+    output_dir: &Path,
+    options: &ConversionOptions,
+) -> Result<Vec<PathBuf>, ConverterError> {
+    let (frame_width, frame_height) = frame_size;
+    if frame_width == 0 || frame_height == 0 {
+        return Err(ConverterError::failed(Stage::Transform, "Frames need a width and height"));
+    }
+    let bytes = fs::read(sheet_path).map_err(|e| ConverterError::read(sheet_path, e))?;
+    let sheet = pipeline::decode(&bytes, &options.limits)
+        .map_err(|e| e.in_file(sheet_path))?
+        .to_rgba8();
+    let stem = sheet_path.file_stem().unwrap_or_default().to_string_lossy();
+
+    let mut written = Vec::new();
+    for row in 0..sheet.height() / frame_height {
+        for column in 0..sheet.width() / frame_width {
+            let index = row * (sheet.width() / frame_width) + column;
+            let cell = imageops::crop_imm(&sheet, column * frame_width, row * frame_height, frame_width, frame_height)
+                .to_image();
+            if cell.pixels().all(|pixel| pixel[3] == 0) {
+                continue;
+            }
+            let name = format!("{}_{}.png", stem, index);
+            written.push(write_frame(&DynamicImage::ImageRgba8(cell), output_dir, &name, options)?);
+        }
+    }
+    Ok(written)
+}
+
+/// Whether joining `path` onto a directory stays inside it: no root, prefix or `..`
+fn is_relative_inside(path: &Path) -> bool {
+    path.components().all(|component| matches!(component, Component::Normal(_)))
+}
+
+/// Frame names may contain folders, but never leave `output_dir`
+fn write_frame(
+    img: &DynamicImage,
+    output_dir: &Path,
+    name: &str,
+    options: &ConversionOptions,
+) -> Result<PathBuf, ConverterError> {
+    let relative = Path::new(name);
+    if !is_relative_inside(relative) {
+        return Err(ConverterError::failed(Stage::Write, format!("Frame name {} leaves the output directory", name)));
+    }
+    let (path, format) = match ImageFormat::from_extension(Some(name)) {
+        Some(format) => (output_dir.join(relative), format),
+        None => (output_dir.join(format!("{}.png", name)), ImageFormat::PNG),
+    };
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).map_err(|e| ConverterError::write(parent, e))?;
+    }
+    let bytes = pipeline::encode(img, &format, options)?;
+    output::write_atomic(&path, &bytes)?;
+    Ok(path)
+}
@@ -0,0 +1,166 @@
+use crate::converter::errors::{ConverterError, Stage};
+use image::{DynamicImage, GenericImageView, GrayImage, Rgba, RgbaImage};
+use serde::Serialize;
+use std::fs;
+use std::path::Path;
+
+/// Side length of the square windows SSIM is averaged over
+const SSIM_WINDOW: u32 = 8;
+/// Distance between neighbouring SSIM windows
+const SSIM_STRIDE: u32 = 4;
+const SSIM_C1: f64 = (0.01 * 255.0) * (0.01 * 255.0);
+const SSIM_C2: f64 = (0.03 * 255.0) * (0.03 * 255.0);
+
+/// Quality and size of a converted image measured against its source
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Comparison {
+    /// Peak signal-to-noise ratio in dB, infinite for identical pixels
+    pub psnr: f64,
+    /// Structural similarity, 1.0 for identical pixels
+    pub ssim: f64,
+    pub reference_bytes: u64,
+    pub distorted_bytes: u64,
+}
+
+impl Comparison {
+    /// Fraction of the reference size saved by the distorted file, negative if it grew
+    pub fn savings(&self) -> f64 {
+        if self.reference_bytes == 0 {
+            return 0.0;
+        }
+        1.0 - self.distorted_bytes as f64 / self.reference_bytes as f64
+    }
+}
+
+/// Compare a converted file against the file it was converted from
+pub fn compare(reference: &Path, distorted: &Path) -> Result<Comparison, ConverterError> {
+    let reference_bytes = fs::read(reference)
+        .map_err(|e| ConverterError::read(reference, e))?;
+    let distorted_bytes = fs::read(distorted)
+        .map_err(|e| ConverterError::read(distorted, e))?;
+    let reference_img = image::load_from_memory(&reference_bytes)
+        .map_err(|e| ConverterError::decode(e).in_file(reference))?;
+    let distorted_img = image::load_from_memory(&distorted_bytes)
+        .map_err(|e| ConverterError::decode(e).in_file(distorted))?;
+
+    Ok(Comparison {
+        psnr: psnr(&reference_img, &distorted_img)?,
+        ssim: ssim(&reference_img, &distorted_img)?,
+        reference_bytes: reference_bytes.len() as u64,
+        distorted_bytes: distorted_bytes.len() as u64,
+    })
+}
+
+fn check_dimensions(a: &DynamicImage, b: &DynamicImage) -> Result<(), ConverterError> {
+    if a.dimensions() == b.dimensions() {
+        Ok(())
+    } else {
+        Err(ConverterError::failed(Stage::Transform, format!(
+            "Cannot compare a {}x{} image with a {}x{} image",
+            a.width(), a.height(), b.width(), b.height()
+        )))
+    }
+}
+
+/// Peak signal-to-noise ratio over the RGB channels, in dB
+pub fn psnr(reference: &DynamicImage, distorted: &DynamicImage) -> Result<f64, ConverterError> {
+    check_dimensions(reference, distorted)?;
+    let a = reference.to_rgb8();
+    let b = distorted.to_rgb8();
+
+    let squared_error: f64 = a
+        .as_raw()
+        .iter()
+        .zip(b.as_raw())
+        .map(|(&x, &y)| (x as f64 - y as f64).powi(2))
+        .sum();
+    let mse = squared_error / a.as_raw().len().max(1) as f64;
+    if mse == 0.0 {
+        return Ok(f64::INFINITY);
+    }
+    Ok(10.0 * (255.0 * 255.0 / mse).log10())
+}
+
+/// Mean structural similarity of the luma channel over overlapping windows
+pub fn ssim(reference: &DynamicImage, distorted: &DynamicImage) -> Result<f64, ConverterError> {
+    check_dimensions(reference, distorted)?;
+    let a = reference.to_luma8();
+    let b = distorted.to_luma8();
+    let (width, height) = a.dimensions();
+
+    // Images smaller than one window are compared as a whole
+    let window_w = SSIM_WINDOW.min(width);
+    let window_h = SSIM_WINDOW.min(height);
+
+    let mut total = 0.0;
+    let mut windows = 0usize;
+    let mut y = 0;
+    while y + window_h <= height {
+        let mut x = 0;
+        while x + window_w <= width {
+            total += window_ssim(&a, &b, x, y, window_w, window_h);
+            windows += 1;
+            x += SSIM_STRIDE;
+        }
+        y += SSIM_STRIDE;
+    }
+
+    if windows == 0 {
+        return Ok(1.0);
+    }
+    Ok(total / windows as f64)
+}
+
+fn window_ssim(a: &GrayImage, b: &GrayImage, x0: u32, y0: u32, w: u32, h: u32) -> f64 {
+    let n = (w * h) as f64;
+    let (mut sum_a, mut sum_b, mut sum_aa, mut sum_bb, mut sum_ab) = (0.0, 0.0, 0.0, 0.0, 0.0);
+    for y in y0..y0 + h {
+        for x in x0..x0 + w {
+            let pa = a.get_pixel(x, y)[0] as f64;
+            let pb = b.get_pixel(x, y)[0] as f64;
+            sum_a += pa;
+            sum_b += pb;
+            sum_aa += pa * pa;
+            sum_bb += pb * pb;
+            sum_ab += pa * pb;
+        }
+    }
+
+    let mean_a = sum_a / n;
+    let mean_b = sum_b / n;
+    let var_a = sum_aa / n - mean_a * mean_a;
+    let var_b = sum_bb / n - mean_b * mean_b;
+    let covariance = sum_ab / n - mean_a * mean_b;
+
+    ((2.0 * mean_a * mean_b + SSIM_C1) * (2.0 * covariance + SSIM_C2))
+        / ((mean_a * mean_a + mean_b * mean_b + SSIM_C1) * (var_a + var_b + SSIM_C2))
+}
+
+/// Heat-map of per-pixel differences: black where the images agree,
+/// through blue and red to yellow for the largest differences
+pub fn difference_map(reference: &DynamicImage, distorted: &DynamicImage) -> Result<RgbaImage, ConverterError> {
+    check_dimensions(reference, distorted)?;
+    let a = reference.to_rgb8();
+    let b = distorted.to_rgb8();
+
+    Ok(RgbaImage::from_fn(a.width(), a.height(), |x, y| {
+        let pa = a.get_pixel(x, y);
+        let pb = b.get_pixel(x, y);
+        let diff = (0..3).map(|c| pa[c].abs_diff(pb[c])).max().unwrap_or(0);
+        heat_color(diff)
+    }))
+}
+
+fn heat_color(diff: u8) -> Rgba<u8> {
+    // Small differences are the interesting ones, so stretch the low end
+    let t = ((diff as f64 / 255.0).sqrt() * 3.0).min(3.0);
+    let channel = |v: f64| (v.clamp(0.0, 1.0) * 255.0) as u8;
+    let (r, g, b) = if t < 1.0 {
+        (0.0, 0.0, t)
+    } else if t < 2.0 {
+        (t - 1.0, 0.0, 2.0 - t)
+    } else {
+        (1.0, t - 2.0, 0.0)
+    };
+    Rgba([channel(r), channel(g), channel(b), 255])
+}
@@ -1,15 +1,184 @@
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Step of a conversion an error came from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stage {
+    Read,
+    Decode,
+    Transform,
+    Encode,
+    Write,
+}
+
+impl fmt::Display for Stage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Stage::Read => "Reading",
+            Stage::Decode => "Decoding",
+            Stage::Transform => "Transforming",
+            Stage::Encode => "Encoding",
+            Stage::Write => "Writing",
+        };
+        f.write_str(name)
+    }
+}
+
+/// Which of the `InputLimits` an input broke
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LimitKind {
+    Width,
+    Height,
+    Pixels,
+    Allocation,
+    InputBytes,
+    Frames,
+}
+
+impl fmt::Display for LimitKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            LimitKind::Width => "width",
+            LimitKind::Height => "height",
+            LimitKind::Pixels => "pixel count",
+            LimitKind::Allocation => "memory allocation",
+            LimitKind::InputBytes => "input size",
+            LimitKind::Frames => "frame count",
+        };
+        f.write_str(name)
+    }
+}
+
+/// " path" when the error knows its file, for the messages below
+fn at(path: &Option<PathBuf>) -> String {
+    path.as_ref()
+        .map(|path| format!(" {}", path.display()))
+        .unwrap_or_default()
+}
+
 #[derive(thiserror::Error, Debug)]
 pub enum ConverterError {
     #[error("unsupported format: {0}")]
     UnsupportedFormat(String),
-    #[error("I/O error: {0}")]
-    Io(#[from] std::io::Error),
-    #[error("Image processing error: {0}")]
-    ImageError(#[from] image::ImageError),
-    #[error("Read error: {0}")]
-    ReadError(String),
-    #[error("Conversion error: {0}")]
-    ConversionError(String),
-    #[error("Write error: {0}")]
-    WriteError(String),
+    #[error("{stage}{} failed: {source}", at(path))]
+    Io {
+        path: Option<PathBuf>,
+        stage: Stage,
+        source: io::Error,
+    },
+    #[error("{stage}{} failed: {source}", at(path))]
+    Image {
+        path: Option<PathBuf>,
+        stage: Stage,
+        source: image::ImageError,
+    },
+    /// Failures reported only as text, e.g. by libwebp, mozjpeg or oxipng
+    #[error("{stage}{} failed: {message}", at(path))]
+    Failed {
+        path: Option<PathBuf>,
+        stage: Stage,
+        message: String,
+    },
+    /// The input is larger than the `InputLimits` allow; nothing was decoded past the check
+    #[error("Input{} exceeds the {kind} limit of {max}", at(path))]
+    LimitExceeded {
+        path: Option<PathBuf>,
+        kind: LimitKind,
+        max: u64,
+    },
+    #[error("Invalid name template: {0}")]
+    InvalidTemplate(String),
+    #[error("Output file already exists: {}", .0.display())]
+    OutputExists(PathBuf),
+    #[error("Disk full while writing {}", .0.display())]
+    DiskFull(PathBuf),
+    #[error("Cannot fit under {limit} bytes, smallest attempt was {smallest} bytes")]
+    TooLarge { limit: u64, smallest: u64 },
+    #[error("Cannot reach SSIM {target}, the best quality scores {achieved:.4}")]
+    SsimUnreachable { target: f64, achieved: f64 },
+}
+
+impl ConverterError {
+    pub fn read(path: &Path, source: io::Error) -> Self {
+        ConverterError::Io { path: Some(path.to_path_buf()), stage: Stage::Read, source }
+    }
+
+    pub fn write(path: &Path, source: io::Error) -> Self {
+        ConverterError::Io { path: Some(path.to_path_buf()), stage: Stage::Write, source }
+    }
+
+    pub fn decode(source: image::ImageError) -> Self {
+        ConverterError::Image { path: None, stage: Stage::Decode, source }
+    }
+
+    pub fn encode(source: image::ImageError) -> Self {
+        ConverterError::Image { path: None, stage: Stage::Encode, source }
+    }
+
+    pub fn limit(kind: LimitKind, max: u64) -> Self {
+        ConverterError::LimitExceeded { path: None, kind, max }
+    }
+
+    pub fn failed(stage: Stage, message: impl Into<String>) -> Self {
+        ConverterError::Failed { path: None, stage, message: message.into() }
+    }
+
+    /// Attach the file being converted, unless the error already names one
+    pub fn in_file(mut self, file: &Path) -> Self {
+        if let ConverterError::Io { path, .. }
+        | ConverterError::Image { path, .. }
+        | ConverterError::Failed { path, .. }
+        | ConverterError::LimitExceeded { path, .. } = &mut self
+            && path.is_none()
+        {
+            *path = Some(file.to_path_buf());
+        }
+        self
+    }
+
+    /// The step that failed, for errors raised while converting
+    pub fn stage(&self) -> Option<Stage> {
+        match self {
+            ConverterError::Io { stage, .. }
+            | ConverterError::Image { stage, .. }
+            | ConverterError::Failed { stage, .. } => Some(*stage),
+            ConverterError::LimitExceeded { .. } => Some(Stage::Decode),
+            ConverterError::OutputExists(_) | ConverterError::DiskFull(_) => Some(Stage::Write),
+            _ => None,
+        }
+    }
+
+    /// The file the error is about, if known
+    pub fn path(&self) -> Option<&Path> {
+        match self {
+            ConverterError::Io { path, .. }
+            | ConverterError::Image { path, .. }
+            | ConverterError::Failed { path, .. }
+            | ConverterError::LimitExceeded { path, .. } => path.as_deref(),
+            ConverterError::OutputExists(path) | ConverterError::DiskFull(path) => Some(path),
+            _ => None,
+        }
+    }
+
+    /// The format or a feature of it is not supported, as opposed to broken input
+    pub fn is_unsupported(&self) -> bool {
+        matches!(
+            self,
+            ConverterError::UnsupportedFormat(_)
+                | ConverterError::Image { source: image::ImageError::Unsupported(_), .. }
+        )
+    }
+
+    /// The input decoded as a known format but its data is damaged or truncated
+    pub fn is_corrupt_input(&self) -> bool {
+        matches!(
+            self,
+            ConverterError::Image {
+                stage: Stage::Decode,
+                source: image::ImageError::Decoding(_) | image::ImageError::IoError(_),
+                ..
+            }
+        )
+    }
 }
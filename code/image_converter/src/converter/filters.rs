@@ -0,0 +1,184 @@
+use image::{imageops, DynamicImage, Rgba, RgbaImage};
+use crate::converter::options::Filter;
+
+/// Run `filters` over `img` in order. An image without alpha stays without alpha;
+/// anything deeper than 8 bits per channel comes back as 8 bits.
+pub fn apply(img: &DynamicImage, filters: &[Filter]) -> DynamicImage {
+    let mut pixels = img.to_rgba8();
+    for filter in filters {
+        pixels = match *filter {
+            Filter::Brightness(amount) => imageops::brighten(&pixels, amount),
+            Filter::Contrast(percent) => imageops::contrast(&pixels, percent),
+            Filter::Gamma(gamma) => map_channels(pixels, |value| {
+                (255.0 * (value as f64 / 255.0).powf(1.0 / gamma.max(0.01) as f64)).round() as u8
+            }),
+            Filter::Saturation(factor) => map_pixels(pixels, |[r, g, b]| {
+                let gray = luma([r, g, b]);
+                [r, g, b].map(|channel| gray + (channel - gray) * factor)
+            }),
+            Filter::HueRotate(degrees) => imageops::huerotate(&pixels, degrees),
+            Filter::Grayscale => map_pixels(pixels, |rgb| [luma(rgb); 3]),
+            Filter::Sepia => map_pixels(pixels, |[r, g, b]| {
+                [
+                    0.393 * r + 0.769 * g + 0.189 * b,
+                    0.349 * r + 0.686 * g + 0.168 * b,
+                    0.272 * r + 0.534 * g + 0.131 * b,
+                ]
+            }),
+            Filter::Invert => {
+                imageops::invert(&mut pixels);
+                pixels
+            }
+            Filter::AutoLevels => auto_levels(pixels),
+            Filter::Blur(sigma) => imageops::blur(&pixels, sigma),
+            Filter::Sharpen { sigma, threshold } => imageops::unsharpen(&pixels, sigma, threshold),
+        };
+    }
+    if img.color().has_alpha() {
+        DynamicImage::ImageRgba8(pixels)
+    } else {
+        DynamicImage::ImageRgb8(DynamicImage::ImageRgba8(pixels).to_rgb8())
+    }
+}
+
+/// Rec. 709 luma of an RGB triple
+fn luma([r, g, b]: [f32; 3]) -> f32 {
+    0.2126 * r + 0.7152 * g + 0.0722 * b
+}
+
+/// Replace the color of every pixel, leaving alpha alone. Results are clamped to 0-255.
+fn map_pixels(mut pixels: RgbaImage, f: impl Fn([f32; 3]) -> [f32; 3]) -> RgbaImage {
+    for pixel in pixels.pixels_mut() {
+        let [r, g, b, a] = pixel.0;
+        let [r, g, b] = f([r as f32, g as f32, b as f32]).map(|value| value.round().clamp(0.0, 255.0) as u8);
+        *pixel = Rgba([r, g, b, a]);
+    }
+    pixels
+}
+
+/// Map each color channel through a lookup table built from `f`
+fn map_channels(mut pixels: RgbaImage, f: impl Fn(u8) -> u8) -> RgbaImage {
+    let table: Vec<u8> = (0..=255).map(f).collect();
+    for pixel in pixels.pixels_mut() {
+        for channel in 0..3 {
+            pixel[channel] = table[pixel[channel] as usize];
+        }
+    }
+    pixels
+}
+
+/// Share of the darkest and brightest pixels of each channel `auto_levels` ignores,
+/// so a few stray pixels don't stop the stretch
+const LEVELS_CLIP: f64 = 0.005;
+
+/// Stretch each color channel so its range covers 0-255. Fully transparent
+/// pixels don't count.
+fn auto_levels(mut pixels: RgbaImage) -> RgbaImage {
+    let mut histograms = [[0u64; 256]; 3];
+    let mut counted = 0u64;
+    for pixel in pixels.pixels().filter(|pixel| pixel[3] != 0) {
+        for channel in 0..3 {
+            histograms[channel][pixel[channel] as usize] += 1;
+        }
+        counted += 1;
+    }
+    let clip = (counted as f64 * LEVELS_CLIP) as u64;
+    let tables = histograms.map(|histogram| {
+        let low = percentile(histogram.iter().enumerate(), clip).unwrap_or(0);
+        let high = percentile(histogram.iter().enumerate().rev(), clip).unwrap_or(255);
+        (0..=255u32)
+            .map(|value| {
+                if high > low {
+                    ((value.clamp(low, high) - low) * 255 / (high - low)) as u8
+                } else {
+                    value as u8
+                }
+            })
+            .collect::<Vec<u8>>()
+    });
+
+    for pixel in pixels.pixels_mut() {
+        for channel in 0..3 {
+            pixel[channel] = tables[channel][pixel[channel] as usize];
+        }
+    }
+    pixels
+}
+
+/// First value, walking the histogram in the given direction, past `skip` pixels
+fn percentile<'a>(mut bins: impl Iterator<Item = (usize, &'a u64)>, skip: u64) -> Option<u32> {
+    let mut seen = 0;
+    bins.find(|&(_, &count)| {
+        seen += count;
+        seen > skip
+    })
+    .map(|(value, _)| value as u32)
+}
+
+/// Read a comma-separated filter list such as `"brightness 10, sharpen 1 5, sepia"`.
+/// Names are case-insensitive; see `describe` for the form of each.
+pub fn parse(text: &str) -> Result<Vec<Filter>, String> {
+    text.split(',')
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+        .map(parse_one)
+        .collect()
+}
+
+fn parse_one(item: &str) -> Result<Filter, String> {
+    let lower = item.to_lowercase();
+    let mut words = lower.split_whitespace();
+    let name = words.next().unwrap_or_default();
+    let args: Vec<&str> = words.collect();
+    let number = |index: usize| -> Result<f32, String> {
+        args.get(index)
+            .and_then(|arg| arg.parse::<f32>().ok())
+            .filter(|value| value.is_finite())
+            .ok_or_else(|| format!("\"{}\" needs a number", item))
+    };
+    let positive = |index: usize| -> Result<f32, String> {
+        number(index)
+            .ok()
+            .filter(|&value| value > 0.0)
+            .ok_or_else(|| format!("\"{}\" needs a number above 0", item))
+    };
+    let filter = match name {
+        "brightness" => Filter::Brightness(number(0)?.round() as i32),
+        "contrast" => Filter::Contrast(number(0)?),
+        "gamma" => Filter::Gamma(positive(0)?),
+        "saturation" => Filter::Saturation(number(0)?),
+        "hue" => Filter::HueRotate(number(0)?.round() as i32),
+        "grayscale" | "greyscale" => Filter::Grayscale,
+        "sepia" => Filter::Sepia,
+        "invert" => Filter::Invert,
+        "autolevels" => Filter::AutoLevels,
+        "blur" => Filter::Blur(positive(0)?),
+        "sharpen" => Filter::Sharpen {
+            sigma: positive(0)?,
+            threshold: args.get(1).map_or(Ok(0.0), |_| number(1))?.round() as i32,
+        },
+        _ => return Err(format!("unknown filter \"{}\"", item)),
+    };
+    Ok(filter)
+}
+
+/// `filters` in the form `parse` reads back
+pub fn describe(filters: &[Filter]) -> String {
+    filters
+        .iter()
+        .map(|filter| match filter {
+            Filter::Brightness(amount) => format!("brightness {}", amount),
+            Filter::Contrast(percent) => format!("contrast {}", percent),
+            Filter::Gamma(gamma) => format!("gamma {}", gamma),
+            Filter::Saturation(factor) => format!("saturation {}", factor),
+            Filter::HueRotate(degrees) => format!("hue {}", degrees),
+            Filter::Grayscale => "grayscale".to_string(),
+            Filter::Sepia => "sepia".to_string(),
+            Filter::Invert => "invert".to_string(),
+            Filter::AutoLevels => "autolevels".to_string(),
+            Filter::Blur(sigma) => format!("blur {}", sigma),
+            Filter::Sharpen { sigma, threshold } => format!("sharpen {} {}", sigma, threshold),
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
@@ -1,3 +1,7 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum ImageFormat {
     PNG,
@@ -8,6 +12,15 @@ pub enum ImageFormat {
 }
 
 impl ImageFormat {
+    /// Every supported format, in the order the UI lists them
+    pub const ALL: [ImageFormat; 5] = [
+        ImageFormat::PNG,
+        ImageFormat::JPEG,
+        ImageFormat::WEBP,
+        ImageFormat::GIF,
+        ImageFormat::BMP,
+    ];
+
     fn get_extension(filename: &str) -> &str {
         filename.rsplit('.').next().unwrap_or("")
     }
@@ -15,11 +28,11 @@ impl ImageFormat {
     pub fn from_extension(filename_opt: Option<&str>) -> Option<Self> {
         let mut result = None;
         if let Some(filename) = filename_opt { 
-        let ext = Self::get_extension(&filename);
+        let ext = Self::get_extension(filename);
         let capitalized = ext.to_uppercase();
         result = match capitalized.as_str() {
             "PNG" => Some(ImageFormat::PNG),
-            "JPEG" => Some(ImageFormat::JPEG),
+            "JPG" | "JPEG" => Some(ImageFormat::JPEG),
             "WEBP" => Some(ImageFormat::WEBP),
             "GIF" => Some(ImageFormat::GIF),
             "BMP" => Some(ImageFormat::BMP),
@@ -29,6 +42,28 @@ impl ImageFormat {
     result
     }
       
+    /// Recognise the format from the first bytes of an encoded image
+    pub fn detect(bytes: &[u8]) -> Option<Self> {
+        match image::guess_format(bytes).ok()? {
+            image::ImageFormat::Png => Some(ImageFormat::PNG),
+            image::ImageFormat::Jpeg => Some(ImageFormat::JPEG),
+            image::ImageFormat::WebP => Some(ImageFormat::WEBP),
+            image::ImageFormat::Gif => Some(ImageFormat::GIF),
+            image::ImageFormat::Bmp => Some(ImageFormat::BMP),
+            _ => None,
+        }
+    }
+
+    pub fn mime_type(&self) -> &'static str {
+        match self {
+            ImageFormat::PNG => "image/png",
+            ImageFormat::JPEG => "image/jpeg",
+            ImageFormat::WEBP => "image/webp",
+            ImageFormat::GIF => "image/gif",
+            ImageFormat::BMP => "image/bmp",
+        }
+    }
+
     pub fn to_extension(&self) -> &str {
         match self {
             ImageFormat::PNG => "png",
@@ -39,3 +74,21 @@ impl ImageFormat {
         }
     }
 }
+
+/// Entries of `dir` that are subdirectories or files with a supported image
+/// extension: directories first, then files, both alphabetically
+pub fn read_image_dir(dir: &Path) -> io::Result<Vec<fs::DirEntry>> {
+    let is_dir = |entry: &fs::DirEntry| entry.metadata().map(|m| m.is_dir()).unwrap_or(false);
+    let mut entries: Vec<fs::DirEntry> = fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| {
+            is_dir(entry)
+                || entry
+                    .file_name()
+                    .to_str()
+                    .is_some_and(|name| ImageFormat::from_extension(Some(name)).is_some())
+        })
+        .collect();
+    entries.sort_by(|a, b| is_dir(b).cmp(&is_dir(a)).then_with(|| a.file_name().cmp(&b.file_name())));
+    Ok(entries)
+}
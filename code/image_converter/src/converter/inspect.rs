@@ -0,0 +1,201 @@
+use crate::converter::errors::ConverterError;
+use image::codecs::{gif::GifDecoder, png::PngDecoder, webp::WebPDecoder};
+use image::{AnimationDecoder, DynamicImage, ImageDecoder, ImageFormat as ImgFmt, ImageReader};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::Cursor;
+use std::path::{Path, PathBuf};
+
+/// EXIF fields worth showing at a glance
+const KEY_EXIF_TAGS: [exif::Tag; 12] = [
+    exif::Tag::Make,
+    exif::Tag::Model,
+    exif::Tag::LensModel,
+    exif::Tag::DateTimeOriginal,
+    exif::Tag::Orientation,
+    exif::Tag::ExposureTime,
+    exif::Tag::FNumber,
+    exif::Tag::PhotographicSensitivity,
+    exif::Tag::FocalLength,
+    exif::Tag::Software,
+    exif::Tag::GPSLatitude,
+    exif::Tag::GPSLongitude,
+];
+
+/// Properties of an image file, as reported by `inspect`
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ImageInfo {
+    pub path: PathBuf,
+    pub file_size: u64,
+    /// Format detected from the file contents, not the extension
+    pub format: String,
+    pub width: u32,
+    pub height: u32,
+    pub color_type: String,
+    /// Bits per channel
+    pub bit_depth: u16,
+    pub frame_count: usize,
+    pub has_alpha_channel: bool,
+    /// Whether any pixel is actually less than fully opaque
+    pub alpha_used: bool,
+    pub icc_profile: Option<String>,
+    pub exif: BTreeMap<String, String>,
+}
+
+impl ImageInfo {
+    /// Human readable label/value pairs, in display order
+    pub fn fields(&self) -> Vec<(String, String)> {
+        let mut fields = vec![
+            ("Format".to_string(), self.format.clone()),
+            ("Dimensions".to_string(), format!("{} x {}", self.width, self.height)),
+            ("Color type".to_string(), self.color_type.clone()),
+            ("Bit depth".to_string(), format!("{} bits per channel", self.bit_depth)),
+            ("File size".to_string(), format!("{} bytes", self.file_size)),
+            ("Frames".to_string(), self.frame_count.to_string()),
+            (
+                "Alpha".to_string(),
+                match (self.has_alpha_channel, self.alpha_used) {
+                    (false, _) => "none".to_string(),
+                    (true, false) => "channel present, fully opaque".to_string(),
+                    (true, true) => "used".to_string(),
+                },
+            ),
+            (
+                "ICC profile".to_string(),
+                self.icc_profile.clone().unwrap_or_else(|| "none".to_string()),
+            ),
+        ];
+        fields.extend(self.exif.iter().map(|(tag, value)| (tag.clone(), value.clone())));
+        fields
+    }
+}
+
+/// Read an image file and describe its dimensions, pixel layout and metadata
+pub fn inspect(path: &Path) -> Result<ImageInfo, ConverterError> {
+    let bytes = fs::read(path)
+        .map_err(|e| ConverterError::read(path, e))?;
+    let format = image::guess_format(&bytes)
+        .map_err(|e| ConverterError::UnsupportedFormat(e.to_string()))?;
+
+    let mut decoder = ImageReader::with_format(Cursor::new(&bytes), format)
+        .into_decoder()
+        .map_err(|e| ConverterError::decode(e).in_file(path))?;
+    let icc_profile = decoder
+        .icc_profile()
+        .ok()
+        .flatten()
+        .and_then(|profile| icc_description(&profile));
+    let exif = decoder
+        .exif_metadata()
+        .ok()
+        .flatten()
+        .map(exif_fields)
+        .unwrap_or_default();
+
+    let img = DynamicImage::from_decoder(decoder)
+        .map_err(|e| ConverterError::decode(e).in_file(path))?;
+    let color = img.color();
+
+    Ok(ImageInfo {
+        path: path.to_path_buf(),
+        file_size: bytes.len() as u64,
+        format: format!("{:?}", format).to_uppercase(),
+        width: img.width(),
+        height: img.height(),
+        color_type: format!("{:?}", color),
+        bit_depth: color.bits_per_pixel() / color.channel_count() as u16,
+        frame_count: frame_count(&bytes, format, usize::MAX).map_err(|e| e.in_file(path))?,
+        has_alpha_channel: color.has_alpha(),
+        alpha_used: color.has_alpha() && img.to_rgba8().pixels().any(|p| p[3] < u8::MAX),
+        icc_profile,
+        exif,
+    })
+}
+
+/// Number of frames in an animation, counting no further than `stop_after`
+pub(crate) fn frame_count(bytes: &[u8], format: ImgFmt, stop_after: usize) -> Result<usize, ConverterError> {
+    let count = match format {
+        ImgFmt::Gif => GifDecoder::new(Cursor::new(bytes))
+            .map_err(ConverterError::decode)?
+            .into_frames()
+            .take(stop_after)
+            .count(),
+        ImgFmt::WebP => {
+            let decoder = WebPDecoder::new(Cursor::new(bytes))
+                .map_err(ConverterError::decode)?;
+            if decoder.has_animation() { decoder.into_frames().take(stop_after).count() } else { 1 }
+        }
+        ImgFmt::Png => {
+            let decoder = PngDecoder::new(Cursor::new(bytes))
+                .map_err(ConverterError::decode)?;
+            if decoder.is_apng().unwrap_or(false) {
+                decoder
+                    .apng()
+                    .map_err(ConverterError::decode)?
+                    .into_frames()
+                    .take(stop_after)
+                    .count()
+            } else {
+                1
+            }
+        }
+        _ => 1,
+    };
+    Ok(count)
+}
+
+fn exif_fields(raw: Vec<u8>) -> BTreeMap<String, String> {
+    let Ok(exif) = exif::Reader::new().read_raw(raw) else {
+        return BTreeMap::new();
+    };
+    KEY_EXIF_TAGS
+        .iter()
+        .filter_map(|&tag| exif.get_field(tag, exif::In::PRIMARY))
+        .map(|field| {
+            let value = field.display_value().with_unit(&exif).to_string();
+            (field.tag.to_string(), value)
+        })
+        .collect()
+}
+
+/// Read the profile description ('desc' tag) out of an ICC profile
+fn icc_description(profile: &[u8]) -> Option<String> {
+    let read_u32 = |offset: usize| -> Option<usize> {
+        let bytes = profile.get(offset..offset + 4)?;
+        Some(u32::from_be_bytes(bytes.try_into().ok()?) as usize)
+    };
+
+    // A corrupt count can't make us scan past the end of the profile
+    let tag_count = read_u32(128)?.min(profile.len().saturating_sub(132) / 12);
+    let (offset, size) = (0..tag_count).find_map(|i| {
+        let entry = 132 + i * 12;
+        if profile.get(entry..entry + 4)? != b"desc" {
+            return None;
+        }
+        Some((read_u32(entry + 4)?, read_u32(entry + 8)?))
+    })?;
+    let tag = profile.get(offset..offset.checked_add(size)?)?;
+
+    let description = match tag.get(0..4)? {
+        // ICC v2: ASCII text with its length in front
+        b"desc" => {
+            let length = u32::from_be_bytes(tag.get(8..12)?.try_into().ok()?) as usize;
+            let text = tag.get(12..12 + length)?;
+            String::from_utf8_lossy(text).trim_end_matches('\0').to_string()
+        }
+        // ICC v4: UTF-16BE strings keyed by language, take the first one
+        b"mluc" => {
+            let length = u32::from_be_bytes(tag.get(20..24)?.try_into().ok()?) as usize;
+            let start = u32::from_be_bytes(tag.get(24..28)?.try_into().ok()?) as usize;
+            let units: Vec<u16> = tag
+                .get(start..start + length)?
+                .chunks_exact(2)
+                .map(|pair| u16::from_be_bytes([pair[0], pair[1]]))
+                .collect();
+            String::from_utf16_lossy(&units)
+        }
+        _ => return None,
+    };
+    Some(description)
+}
@@ -1,57 +1,77 @@
 use crate::converter::errors::ConverterError;
-use image::{codecs::webp::WebPEncoder, ExtendedColorType, DynamicImage, ImageFormat as ImgFmt};
-use std::io::Cursor;
+use crate::converter::formats::ImageFormat;
+use crate::converter::options::{ConversionOptions, JpegOptions};
+use crate::converter::pipeline;
+use image::DynamicImage;
 
 /// Convert JPEG to PNG format
-pub fn convert_jpeg_to_png(input: Vec<u8>) -> Result<Vec<u8>, ConverterError> {
-    let dyn_img: DynamicImage = image::load_from_memory(&input)
-        .map_err(|e| ConverterError::ConversionError(e.to_string()))?;
-    let rgba = dyn_img.to_rgba8();
-    let (w, h) = (rgba.width(), rgba.height());
-    let mut out_buf = Vec::new();
-    DynamicImage::ImageRgba8(image::ImageBuffer::from_raw(w, h, rgba.into_raw()).unwrap())
-        .write_to(&mut Cursor::new(&mut out_buf), ImgFmt::Png)
-        .map_err(|e| ConverterError::WriteError(e.to_string()))?;
-    Ok(out_buf)
+pub fn convert_jpeg_to_png(input: &[u8]) -> Result<Vec<u8>, ConverterError> {
+    pipeline::run(input, &ImageFormat::PNG, &ConversionOptions::default())
 }
 
-/// Convert JPEG to WebP format (lossy)
-pub fn convert_jpeg_to_webp(input: Vec<u8>) -> Result<Vec<u8>, ConverterError> {
-    let dyn_img: DynamicImage = image::load_from_memory(&input)
-        .map_err(|e| ConverterError::ConversionError(e.to_string()))?;
-    let rgba = dyn_img.to_rgba8();
-    let (w, h) = (rgba.width(), rgba.height());
-    let raw_pixels = rgba.into_raw();
-    let mut webp_mem = Cursor::new(Vec::new());
-    let encoder = WebPEncoder::new_lossless(&mut webp_mem);
-    encoder.encode(&raw_pixels, w as u32, h as u32, ExtendedColorType::Rgba8)
-        .map_err(|e| ConverterError::ConversionError(e.to_string()))?;
-    let result = webp_mem.clone().into_inner();
-    Ok(result)
+/// Convert JPEG to WebP format (lossless)
+pub fn convert_jpeg_to_webp(input: &[u8]) -> Result<Vec<u8>, ConverterError> {
+    pipeline::run(input, &ImageFormat::WEBP, &ConversionOptions::default())
 }
 
 /// Convert JPEG to GIF format
-pub fn convert_jpeg_to_gif(input: Vec<u8>) -> Result<Vec<u8>, ConverterError> {
-    let dyn_img: DynamicImage = image::load_from_memory(&input)
-        .map_err(|e| ConverterError::ConversionError(e.to_string()))?;
-    let rgba = dyn_img.to_rgba8();
-    let (w, h) = (rgba.width(), rgba.height());
-    let mut out_buf = Vec::new();
-    DynamicImage::ImageRgba8(image::ImageBuffer::from_raw(w, h, rgba.into_raw()).unwrap())
-        .write_to(&mut Cursor::new(&mut out_buf), ImgFmt::Gif)
-        .map_err(|e| ConverterError::WriteError(e.to_string()))?;
-    Ok(out_buf)
+pub fn convert_jpeg_to_gif(input: &[u8]) -> Result<Vec<u8>, ConverterError> {
+    pipeline::run(input, &ImageFormat::GIF, &ConversionOptions::default())
 }
 
 /// Convert JPEG to BMP format
-pub fn convert_jpeg_to_bmp(input: Vec<u8>) -> Result<Vec<u8>, ConverterError> {
-    let dyn_img: DynamicImage = image::load_from_memory(&input)
-        .map_err(|e| ConverterError::ConversionError(e.to_string()))?;
-    let rgba = dyn_img.to_rgba8();
-    let (w, h) = (rgba.width(), rgba.height());
-    let mut out_buf = Vec::new();
-    DynamicImage::ImageRgba8(image::ImageBuffer::from_raw(w, h, rgba.into_raw()).unwrap())
-        .write_to(&mut Cursor::new(&mut out_buf), ImgFmt::Bmp)
-        .map_err(|e| ConverterError::WriteError(e.to_string()))?;
-    Ok(out_buf)
-}
\ No newline at end of file
+pub fn convert_jpeg_to_bmp(input: &[u8]) -> Result<Vec<u8>, ConverterError> {
+    pipeline::run(input, &ImageFormat::BMP, &ConversionOptions::default())
+}
+
+/// Encode an image as JPEG through mozjpeg, with progressive scans,
+/// optimized Huffman tables and chroma subsampling as requested
+#[cfg(feature = "mozjpeg")]
+pub fn encode_jpeg_advanced(img: &DynamicImage, quality: u8, options: &JpegOptions) -> Result<Vec<u8>, ConverterError> {
+    use crate::converter::errors::Stage;
+    use crate::converter::options::ChromaSubsampling;
+
+    let img = pipeline::jpeg_compatible(img);
+    let (pixels, width, height) = (img.as_bytes(), img.width() as usize, img.height() as usize);
+    let color_space = match *img {
+        DynamicImage::ImageLuma8(_) => mozjpeg::ColorSpace::JCS_GRAYSCALE,
+        _ => mozjpeg::ColorSpace::JCS_RGB,
+    };
+    let options = *options;
+    // mozjpeg reports errors by unwinding out of the C code
+    std::panic::catch_unwind(move || -> std::io::Result<Vec<u8>> {
+        let mut compress = mozjpeg::Compress::new(color_space);
+        compress.set_size(width, height);
+        compress.set_quality(quality.clamp(1, 100) as f32);
+        if options.progressive {
+            compress.set_progressive_mode();
+        } else {
+            // mozjpeg defaults to progressive; dropping the scan script makes it baseline
+            compress.set_optimize_scans(false);
+        }
+        compress.set_optimize_coding(options.optimize_coding);
+        let chroma = match options.subsampling {
+            ChromaSubsampling::Auto => None,
+            ChromaSubsampling::Yuv444 => Some((1, 1)),
+            ChromaSubsampling::Yuv422 => Some((2, 1)),
+            ChromaSubsampling::Yuv420 => Some((2, 2)),
+        };
+        if let Some(size) = chroma {
+            compress.set_chroma_sampling_pixel_sizes(size, size);
+        }
+
+        let mut started = compress.start_compress(Vec::new())?;
+        started.write_scanlines(pixels)?;
+        started.finish()
+    })
+    .map_err(|_| ConverterError::failed(Stage::Encode, "mozjpeg failed to encode the image"))?
+    .map_err(|source| ConverterError::Io { path: None, stage: Stage::Encode, source })
+}
+
+/// Without mozjpeg only the default JPEG settings can be written
+#[cfg(not(feature = "mozjpeg"))]
+pub fn encode_jpeg_advanced(_img: &DynamicImage, _quality: u8, _options: &JpegOptions) -> Result<Vec<u8>, ConverterError> {
+    Err(ConverterError::UnsupportedFormat(
+        "Progressive, optimized or subsampled JPEG needs the mozjpeg feature".to_string()
+    ))
+}
@@ -1,76 +1,692 @@
+use std::borrow::Cow;
 use std::path::{Path, PathBuf};
 use std::fs;
+use std::thread;
+use std::io::{BufReader, BufWriter, Cursor, Read, Seek, SeekFrom, Write};
+use image::{DynamicImage, GenericImageView};
+use image::imageops::FilterType;
 use crate::converter::formats::ImageFormat;
-use crate::converter::errors::ConverterError;
+use crate::converter::errors::{ConverterError, LimitKind, Stage};
+use crate::converter::{compare, naming, output, pipeline, streaming};
+use crate::converter::options::{ConversionOptions, InputLimits};
 use crate::converter::{
     jpeg_converter, png_converter, webp_converter, 
     gif_converter, bmp_converter
 };
 
-// Alternative version that returns PathBuf for better path handling
-fn convert_path_extension_pathbuf(path: &Path, new_extension: &str) -> PathBuf {
-    let clean_extension = new_extension.strip_prefix('.').unwrap_or(new_extension);
-    path.with_extension(clean_extension)
+/// Summary of a finished conversion
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConversionReport {
+    pub output_path: PathBuf,
+    pub input_bytes: u64,
+    pub output_bytes: u64,
+    /// The output already existed and the collision policy said to leave it
+    pub skipped: bool,
+    /// Quality picked to meet `max_bytes`
+    pub chosen_quality: Option<u8>,
+    /// SSIM of the output against the source, when `min_ssim` picked the quality
+    pub achieved_ssim: Option<f64>,
+    /// Size the image was shrunk to to meet `max_bytes`
+    pub downscaled_to: Option<(u32, u32)>,
+    /// Bytes the PNG optimizer removed, if it ran
+    pub optimizer_savings: Option<u64>,
+    /// Copy of the replaced or removed input, if one was made
+    pub backup_path: Option<PathBuf>,
+    /// The input was deleted or moved away after the conversion
+    pub original_removed: bool,
 }
 
-/// Main conversion function that dispatches to appropriate converters
-pub fn convert(input_path: &Path, target_format: &ImageFormat) -> Result<(), ConverterError> {
-    let output_path = convert_path_extension_pathbuf(input_path, target_format.to_extension());
-    if input_path == output_path {
-        return Err(ConverterError::UnsupportedFormat(
-            "Input and output paths are the same".to_string()
+fn is_in_place(input_path: &Path, target_format: &ImageFormat, options: &ConversionOptions) -> bool {
+    options.in_place && !changes_format(input_path, target_format)
+}
+
+/// Whether converting `input_path` to `target_format` gives another format
+fn changes_format(input_path: &Path, target_format: &ImageFormat) -> bool {
+    ImageFormat::from_extension(input_path.to_str()) != Some(*target_format)
+}
+
+/// Width and height from the image header, without decoding the pixels
+fn encoded_dimensions(bytes: &[u8]) -> (u32, u32) {
+    image::ImageReader::new(Cursor::new(bytes))
+        .with_guessed_format()
+        .ok()
+        .and_then(|reader| reader.into_dimensions().ok())
+        .unwrap_or((0, 0))
+}
+
+/// Encode `input_bytes` to every target, decoding and transforming it at most once.
+/// Targets the input already matches pass through without a decode.
+fn encode_many(
+    input_bytes: &[u8],
+    source_format: ImageFormat,
+    targets: &[ImageFormat],
+    options: &ConversionOptions,
+) -> Result<Vec<Result<EncodedOutput, ConverterError>>, ConverterError> {
+    check_searches(options)?;
+    let decoded = if targets.iter().all(|target| is_passthrough(source_format, target, options)) {
+        pipeline::check_limits(input_bytes, &options.limits)?;
+        None
+    } else {
+        Some(pipeline::decode(input_bytes, &options.limits)?)
+    };
+    let img = decoded.as_ref().map(|decoded| pipeline::transform(decoded, options)).transpose()?;
+
+    let encode = |target: &ImageFormat| match &img {
+        Some(img) if !is_passthrough(source_format, target, options) => encode_image(img, target, options),
+        _ => optimize_output(EncodedOutput::plain(input_bytes.to_vec()), target, options),
+    };
+    if !options.parallel || targets.len() < 2 {
+        return Ok(targets.iter().map(encode).collect());
+    }
+    Ok(thread::scope(|scope| {
+        let handles: Vec<_> = targets.iter().map(|target| scope.spawn(|| encode(target))).collect();
+        handles
+            .into_iter()
+            .map(|handle| handle.join().unwrap_or_else(|panic| std::panic::resume_unwind(panic)))
+            .collect()
+    }))
+}
+
+/// Convert encoded image bytes between formats without touching the filesystem
+fn convert_in_memory(
+    input_bytes: Cow<'_, [u8]>,
+    source_format: ImageFormat,
+    target_format: &ImageFormat,
+    options: &ConversionOptions,
+) -> Result<Vec<u8>, ConverterError> {
+    if is_passthrough(source_format, target_format, options) {
+        // Nothing to change, so the bytes pass through untouched
+        pipeline::check_limits(&input_bytes, &options.limits)?;
+        return Ok(input_bytes.into_owned());
+    }
+    if options.needs_reencode() || options.limits != InputLimits::default() {
+        return pipeline::run(&input_bytes, target_format, options);
+    }
+    dispatch(&input_bytes, source_format, target_format)
+}
+
+fn is_passthrough(source_format: ImageFormat, target_format: &ImageFormat, options: &ConversionOptions) -> bool {
+    source_format == *target_format && !options.needs_reencode()
+}
+
+/// Encoded output and what the searches and optimizer decided along the way
+struct EncodedOutput {
+    bytes: Vec<u8>,
+    quality: Option<u8>,
+    ssim: Option<f64>,
+    downscaled_to: Option<(u32, u32)>,
+    optimizer_savings: Option<u64>,
+}
+
+impl EncodedOutput {
+    /// Output of a plain conversion, with no search involved
+    fn plain(bytes: Vec<u8>) -> Self {
+        EncodedOutput { bytes, quality: None, ssim: None, downscaled_to: None, optimizer_savings: None }
+    }
+}
+
+/// Whether the target encoding takes a quality setting
+fn is_lossy_target(target_format: &ImageFormat, options: &ConversionOptions) -> bool {
+    match target_format {
+        ImageFormat::JPEG => true,
+        ImageFormat::WEBP => options.webp.is_lossy(),
+        _ => false,
+    }
+}
+
+/// Run every in-memory step of a conversion: the quality searches or a plain
+/// conversion, then the PNG optimizer
+fn encode_output(
+    input_bytes: Cow<'_, [u8]>,
+    source_format: ImageFormat,
+    target_format: &ImageFormat,
+    options: &ConversionOptions,
+) -> Result<EncodedOutput, ConverterError> {
+    check_searches(options)?;
+    if options.min_ssim.is_none() && options.max_bytes.is_none() {
+        let bytes = convert_in_memory(input_bytes, source_format, target_format, options)?;
+        return optimize_output(EncodedOutput::plain(bytes), target_format, options);
+    }
+    let decoded = pipeline::decode(&input_bytes, &options.limits)?;
+    encode_image(&*pipeline::transform(&decoded, options)?, target_format, options)
+}
+
+/// Encode an image that is already decoded and transformed: the quality searches
+/// or a plain encode, then the PNG optimizer
+fn encode_image(
+    img: &DynamicImage,
+    target_format: &ImageFormat,
+    options: &ConversionOptions,
+) -> Result<EncodedOutput, ConverterError> {
+    let encoded = match (options.min_ssim, options.max_bytes) {
+        (Some(min_ssim), _) => search_ssim(img, target_format, options, min_ssim)?,
+        (None, Some(max_bytes)) => fit_under(img, target_format, options, max_bytes)?,
+        (None, None) => EncodedOutput::plain(pipeline::encode(img, target_format, options)?),
+    };
+    optimize_output(encoded, target_format, options)
+}
+
+/// Refuse `max_bytes` together with `min_ssim`: each searches the quality on its own
+/// terms, and the lowest quality meeting the SSIM is already the smallest output it allows
+fn check_searches(options: &ConversionOptions) -> Result<(), ConverterError> {
+    if options.max_bytes.is_some() && options.min_ssim.is_some() {
+        return Err(ConverterError::failed(
+            Stage::Encode,
+            "A max file size and a min SSIM can't be used together, set only one",
         ));
     }
-    let input_bytes = fs::read(input_path)
-        .map_err(|e| ConverterError::ReadError(e.to_string()))?;
-    
-    let source_format = ImageFormat::from_extension(input_path.to_str())
-        .ok_or_else(|| ConverterError::UnsupportedFormat("Cannot determine input format".to_string()))?;
-    
-    if source_format == *target_format {
-        fs::copy(input_path, output_path)
-            .map_err(|e| ConverterError::WriteError(e.to_string()))?;
-        return Ok(());
-    }
-    
-    let converted_bytes = match (source_format, target_format) {
+    Ok(())
+}
+
+/// Run the PNG optimizer over PNG output when `options` ask for it
+fn optimize_output(
+    mut encoded: EncodedOutput,
+    target_format: &ImageFormat,
+    options: &ConversionOptions,
+) -> Result<EncodedOutput, ConverterError> {
+    if *target_format == ImageFormat::PNG
+        && let Some(level) = options.png_optimization
+    {
+        let optimized = png_converter::optimize_png(&encoded.bytes, level)?;
+        encoded.optimizer_savings = Some(encoded.bytes.len().saturating_sub(optimized.len()) as u64);
+        encoded.bytes = optimized;
+    }
+    Ok(encoded)
+}
+
+/// Smallest image side `max_bytes` will shrink to before giving up
+const MIN_FIT_SIDE: u32 = 16;
+
+/// Encode at the highest quality that fits in `max_bytes`, shrinking the image
+/// when even the lowest quality is too big and `allow_downscale` is set
+fn fit_under(
+    original: &DynamicImage,
+    target_format: &ImageFormat,
+    options: &ConversionOptions,
+    max_bytes: u64,
+) -> Result<EncodedOutput, ConverterError> {
+    let lossy = is_lossy_target(target_format, options);
+    // Shrunk copy of `original`, once the full size has been ruled out
+    let mut resized = None;
+
+    loop {
+        let img = resized.as_ref().unwrap_or(original);
+        let mut smallest = u64::MAX;
+        let mut best = None;
+        if lossy {
+            // Output size grows with quality, so binary search for the highest that fits
+            let (mut low, mut high) = (1u8, 100u8);
+            while low <= high {
+                let quality = low + (high - low) / 2;
+                let attempt = ConversionOptions { quality: Some(quality), ..options.clone() };
+                let bytes = pipeline::encode(img, target_format, &attempt)?;
+                smallest = smallest.min(bytes.len() as u64);
+                if bytes.len() as u64 <= max_bytes {
+                    best = Some((bytes, Some(quality)));
+                    low = quality + 1;
+                } else if quality == 1 {
+                    break;
+                } else {
+                    high = quality - 1;
+                }
+            }
+        } else {
+            let bytes = pipeline::encode(img, target_format, options)?;
+            smallest = bytes.len() as u64;
+            if smallest <= max_bytes {
+                best = Some((bytes, None));
+            }
+        }
+
+        if let Some((bytes, quality)) = best {
+            let downscaled_to = resized.as_ref().map(|img| img.dimensions());
+            return Ok(EncodedOutput { bytes, quality, ssim: None, downscaled_to, optimizer_savings: None });
+        }
+        if !options.allow_downscale || img.width().min(img.height()) <= MIN_FIT_SIDE {
+            return Err(ConverterError::TooLarge { limit: max_bytes, smallest });
+        }
+
+        // Size scales roughly with the pixel count, so shrink both sides by the square root
+        let factor = (max_bytes as f64 / smallest as f64).sqrt().clamp(0.5, 0.9);
+        let width = ((img.width() as f64 * factor) as u32).max(MIN_FIT_SIDE);
+        let height = ((img.height() as f64 * factor) as u32).max(MIN_FIT_SIDE);
+        resized = Some(original.resize_exact(width, height, FilterType::Lanczos3));
+    }
+}
+
+/// Encode at the lowest quality whose SSIM against the source reaches `min_ssim`
+fn search_ssim(
+    original: &DynamicImage,
+    target_format: &ImageFormat,
+    options: &ConversionOptions,
+    min_ssim: f64,
+) -> Result<EncodedOutput, ConverterError> {
+    if !is_lossy_target(target_format, options) {
+        return Err(ConverterError::UnsupportedFormat(format!(
+            "An SSIM target needs a lossy encoding, {:?} output here is lossless",
+            target_format
+        )));
+    }
+    // SSIM grows with quality, so binary search for the lowest that is good enough
+    let (mut low, mut high) = (1u8, 100u8);
+    let mut best = None;
+    let mut highest_score = 0.0f64;
+    while low <= high {
+        let quality = low + (high - low) / 2;
+        let attempt = ConversionOptions { quality: Some(quality), ..options.clone() };
+        let bytes = pipeline::encode(original, target_format, &attempt)?;
+        let score = compare::ssim(original, &pipeline::decode(&bytes, &InputLimits::default())?)?;
+        highest_score = highest_score.max(score);
+        if score >= min_ssim {
+            best = Some((bytes, quality, score));
+            high = quality - 1;
+        } else {
+            low = quality + 1;
+        }
+    }
+
+    let Some((bytes, quality, score)) = best else {
+        return Err(ConverterError::SsimUnreachable { target: min_ssim, achieved: highest_score });
+    };
+    Ok(EncodedOutput {
+        bytes,
+        quality: Some(quality),
+        ssim: Some(score),
+        downscaled_to: None,
+        optimizer_savings: None,
+    })
+}
+
+/// Pick the pairwise converter for the source and target format
+fn dispatch(
+    input_bytes: &[u8],
+    source_format: ImageFormat,
+    target_format: &ImageFormat,
+) -> Result<Vec<u8>, ConverterError> {
+    match (source_format, *target_format) {
         // JPEG
-        (ImageFormat::JPEG, ImageFormat::PNG) => jpeg_converter::convert_jpeg_to_png(input_bytes)?,
-        (ImageFormat::JPEG, ImageFormat::WEBP) => jpeg_converter::convert_jpeg_to_webp(input_bytes)?,
-        (ImageFormat::JPEG, ImageFormat::GIF) => jpeg_converter::convert_jpeg_to_gif(input_bytes)?,
-        (ImageFormat::JPEG, ImageFormat::BMP) => jpeg_converter::convert_jpeg_to_bmp(input_bytes)?,
+        (ImageFormat::JPEG, ImageFormat::PNG) => jpeg_converter::convert_jpeg_to_png(input_bytes),
+        (ImageFormat::JPEG, ImageFormat::WEBP) => jpeg_converter::convert_jpeg_to_webp(input_bytes),
+        (ImageFormat::JPEG, ImageFormat::GIF) => jpeg_converter::convert_jpeg_to_gif(input_bytes),
+        (ImageFormat::JPEG, ImageFormat::BMP) => jpeg_converter::convert_jpeg_to_bmp(input_bytes),
         
         // PNG
-        (ImageFormat::PNG, ImageFormat::JPEG) => png_converter::convert_png_to_jpeg(input_bytes)?,
-        (ImageFormat::PNG, ImageFormat::WEBP) => png_converter::convert_png_to_webp(input_bytes)?,
-        (ImageFormat::PNG, ImageFormat::GIF) => png_converter::convert_png_to_gif(input_bytes)?,
-        (ImageFormat::PNG, ImageFormat::BMP) => png_converter::convert_png_to_bmp(input_bytes)?,
+        (ImageFormat::PNG, ImageFormat::JPEG) => png_converter::convert_png_to_jpeg(input_bytes),
+        (ImageFormat::PNG, ImageFormat::WEBP) => png_converter::convert_png_to_webp(input_bytes),
+        (ImageFormat::PNG, ImageFormat::GIF) => png_converter::convert_png_to_gif(input_bytes),
+        (ImageFormat::PNG, ImageFormat::BMP) => png_converter::convert_png_to_bmp(input_bytes),
         
         // WebP
-        (ImageFormat::WEBP, ImageFormat::PNG) => webp_converter::convert_webp_to_png(input_bytes)?,
-        (ImageFormat::WEBP, ImageFormat::JPEG) => webp_converter::convert_webp_to_jpeg(input_bytes)?,
-        (ImageFormat::WEBP, ImageFormat::GIF) => webp_converter::convert_webp_to_gif(input_bytes)?,
-        (ImageFormat::WEBP, ImageFormat::BMP) => webp_converter::convert_webp_to_bmp(input_bytes)?,
+        (ImageFormat::WEBP, ImageFormat::PNG) => webp_converter::convert_webp_to_png(input_bytes),
+        (ImageFormat::WEBP, ImageFormat::JPEG) => webp_converter::convert_webp_to_jpeg(input_bytes),
+        (ImageFormat::WEBP, ImageFormat::GIF) => webp_converter::convert_webp_to_gif(input_bytes),
+        (ImageFormat::WEBP, ImageFormat::BMP) => webp_converter::convert_webp_to_bmp(input_bytes),
         
         // GIF
-        (ImageFormat::GIF, ImageFormat::PNG) => gif_converter::convert_gif_to_png(input_bytes)?,
-        (ImageFormat::GIF, ImageFormat::JPEG) => gif_converter::convert_gif_to_jpeg(input_bytes)?,
-        (ImageFormat::GIF, ImageFormat::WEBP) => gif_converter::convert_gif_to_webp(input_bytes)?,
-        (ImageFormat::GIF, ImageFormat::BMP) => gif_converter::convert_gif_to_bmp(input_bytes)?,
+        (ImageFormat::GIF, ImageFormat::PNG) => gif_converter::convert_gif_to_png(input_bytes),
+        (ImageFormat::GIF, ImageFormat::JPEG) => gif_converter::convert_gif_to_jpeg(input_bytes),
+        (ImageFormat::GIF, ImageFormat::WEBP) => gif_converter::convert_gif_to_webp(input_bytes),
+        (ImageFormat::GIF, ImageFormat::BMP) => gif_converter::convert_gif_to_bmp(input_bytes),
                 
         // BMP
-        (ImageFormat::BMP, ImageFormat::PNG) => bmp_converter::convert_bmp_to_png(input_bytes)?,
-        (ImageFormat::BMP, ImageFormat::JPEG) => bmp_converter::convert_bmp_to_jpeg(input_bytes)?,
-        (ImageFormat::BMP, ImageFormat::WEBP) => bmp_converter::convert_bmp_to_webp(input_bytes)?,
-        (ImageFormat::BMP, ImageFormat::GIF) => bmp_converter::convert_bmp_to_gif(input_bytes)?,
+        (ImageFormat::BMP, ImageFormat::PNG) => bmp_converter::convert_bmp_to_png(input_bytes),
+        (ImageFormat::BMP, ImageFormat::JPEG) => bmp_converter::convert_bmp_to_jpeg(input_bytes),
+        (ImageFormat::BMP, ImageFormat::WEBP) => bmp_converter::convert_bmp_to_webp(input_bytes),
+        (ImageFormat::BMP, ImageFormat::GIF) => bmp_converter::convert_bmp_to_gif(input_bytes),
         
-        _ => return Err(ConverterError::UnsupportedFormat(
+        _ => Err(ConverterError::UnsupportedFormat(
             format!("Conversion from {:?} to {:?} not supported", source_format, target_format)
         )),
+    }
+}
+
+/// Convert an encoded image held in memory. The source format is detected from the
+/// contents when not given. Options that place files (output directory, name template,
+/// collisions, in-place, backups) have no effect here.
+pub fn convert_bytes(
+    input: &[u8],
+    source_format: Option<ImageFormat>,
+    target_format: &ImageFormat,
+    options: &ConversionOptions,
+) -> Result<Vec<u8>, ConverterError> {
+    let source_format = given_or_detected(source_format, input)?;
+    Ok(encode_output(Cow::Borrowed(input), source_format, target_format, options)?.bytes)
+}
+
+fn given_or_detected(source_format: Option<ImageFormat>, input: &[u8]) -> Result<ImageFormat, ConverterError> {
+    match source_format {
+        Some(format) => Ok(format),
+        None => ImageFormat::detect(input)
+            .ok_or_else(|| ConverterError::UnsupportedFormat("Cannot detect the input format".to_string())),
+    }
+}
+
+/// `convert_bytes` to several formats at once, decoding the input only once; see
+/// `convert_many`. The outputs come back in the order of `targets`.
+pub fn convert_bytes_many(
+    input: &[u8],
+    source_format: Option<ImageFormat>,
+    targets: &[ImageFormat],
+    options: &ConversionOptions,
+) -> Result<Vec<Result<Vec<u8>, ConverterError>>, ConverterError> {
+    let source_format = given_or_detected(source_format, input)?;
+    Ok(encode_many(input, source_format, targets, options)?
+        .into_iter()
+        .map(|encoded| encoded.map(|encoded| encoded.bytes))
+        .collect())
+}
+
+/// Convert an image read from `reader` and write the result to `writer`, which is
+/// handed back once the output has been flushed. See `convert_bytes` for the options.
+pub fn convert_reader<R: Read + Seek, W: Write>(
+    mut reader: R,
+    mut writer: W,
+    source_format: Option<ImageFormat>,
+    target_format: &ImageFormat,
+    options: &ConversionOptions,
+) -> Result<W, ConverterError> {
+    if options.streaming {
+        let source_format = match source_format {
+            Some(format) => format,
+            None => detect_stream_format(&mut reader)?,
+        };
+        return streaming::convert(reader, writer, source_format, target_format, options);
+    }
+
+    let mut input = Vec::new();
+    reader
+        .read_to_end(&mut input)
+        .map_err(|source| ConverterError::Io { path: None, stage: Stage::Read, source })?;
+    let output = convert_bytes(&input, source_format, target_format, options)?;
+    writer
+        .write_all(&output)
+        .and_then(|_| writer.flush())
+        .map_err(|source| ConverterError::Io { path: None, stage: Stage::Write, source })?;
+    Ok(writer)
+}
+
+/// Detect the format from the start of `reader`, leaving it where it was
+fn detect_stream_format<R: Read + Seek>(reader: &mut R) -> Result<ImageFormat, ConverterError> {
+    let mut head = Vec::new();
+    reader
+        .stream_position()
+        .and_then(|start| {
+            reader.by_ref().take(32).read_to_end(&mut head)?;
+            reader.seek(SeekFrom::Start(start))
+        })
+        .map_err(|source| ConverterError::Io { path: None, stage: Stage::Read, source })?;
+    ImageFormat::detect(&head)
+        .ok_or_else(|| ConverterError::UnsupportedFormat("Cannot detect the input format".to_string()))
+}
+
+/// Main conversion function that dispatches to appropriate converters
+pub fn convert(input_path: &Path, target_format: &ImageFormat) -> Result<ConversionReport, ConverterError> {
+    convert_with_options(input_path, target_format, &ConversionOptions::default())
+}
+
+/// Where a converted file goes
+enum Placement {
+    Write { path: PathBuf, in_place: bool },
+    /// The collision policy said to leave the file already at this path
+    Skip(PathBuf),
+}
+
+/// Settle the output path for an image of `dimensions` encoded at `quality`: in place,
+/// or named and placed by `options` with collisions resolved
+fn place_output(
+    input_path: &Path,
+    target_format: &ImageFormat,
+    options: &ConversionOptions,
+    dimensions: (u32, u32),
+    quality: Option<u8>,
+) -> Result<Placement, ConverterError> {
+    if is_in_place(input_path, target_format, options) {
+        return Ok(Placement::Write { path: input_path.to_path_buf(), in_place: true });
+    }
+    let planned_path = naming::planned_output_path(input_path, target_format, options, dimensions, quality)?;
+    let Some(output_path) = naming::resolve_collision(planned_path.clone(), options.collision)? else {
+        return Ok(Placement::Skip(planned_path));
     };
-    
-    fs::write(output_path, converted_bytes)
-        .map_err(|e| ConverterError::WriteError(e.to_string()))?;
-    
+    if input_path == output_path {
+        return Err(ConverterError::failed(
+            Stage::Write,
+            "Input and output paths are the same (use in-place mode to replace the input)",
+        )
+        .in_file(input_path));
+    }
+    Ok(Placement::Write { path: output_path, in_place: false })
+}
+
+/// Write the output through `write`, backing up or removing the input as `options` say;
+/// the input is only removed when the output has another format.
+/// Returns the backup path and whether the input was removed.
+fn store_output<F>(
+    input_path: &Path,
+    output_path: &Path,
+    target_format: &ImageFormat,
+    in_place: bool,
+    options: &ConversionOptions,
+    write: F,
+) -> Result<(Option<PathBuf>, bool), ConverterError>
+where
+    F: FnOnce(&Path) -> Result<(), ConverterError>,
+{
+    if let Some(dir) = output_path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        fs::create_dir_all(dir)
+            .map_err(|e| output::write_error(dir, e))?;
+    }
+
+    let mut backup_path = None;
+    if in_place {
+        backup_path = output::back_up(input_path, &options.backup)?;
+    }
+    write(output_path)?;
+
+    let original_removed = !in_place && options.delete_original && changes_format(input_path, target_format);
+    if original_removed {
+        backup_path = output::retire(input_path, &options.backup)?;
+    }
+    Ok((backup_path, original_removed))
+}
+
+/// Report for a conversion the collision policy skipped
+fn skipped_report(output_path: PathBuf, input_bytes: u64) -> ConversionReport {
+    ConversionReport {
+        output_path,
+        input_bytes,
+        output_bytes: 0,
+        skipped: true,
+        chosen_quality: None,
+        achieved_ssim: None,
+        downscaled_to: None,
+        optimizer_savings: None,
+        backup_path: None,
+        original_removed: false,
+    }
+}
+
+/// Convert a file, choosing the output location, name and collision handling from `options`
+pub fn convert_with_options(
+    input_path: &Path,
+    target_format: &ImageFormat,
+    options: &ConversionOptions,
+) -> Result<ConversionReport, ConverterError> {
+    check_input_size(input_path, options)?;
+    let source_format = source_format_of(input_path)?;
+    if options.streaming {
+        return convert_file_streaming(input_path, source_format, target_format, options);
+    }
+
+    let input_bytes = fs::read(input_path)
+        .map_err(|e| ConverterError::read(input_path, e))?;
+    let input_size = input_bytes.len() as u64;
+
+    let encoded = encode_output(Cow::Owned(input_bytes), source_format, target_format, options)
+        .map_err(|e| e.in_file(input_path))?;
+    write_encoded(input_path, input_size, target_format, options, encoded)
+}
+
+/// Convert a file to several formats, decoding it and applying the transforms only once.
+/// The encoders run on separate threads when `options.parallel` is set. Reading or
+/// decoding the input fails the whole call; each target then succeeds or fails on its own,
+/// in the order of `targets`. With `delete_original`, the input is only removed once
+/// every output has been written.
+pub fn convert_many(
+    input_path: &Path,
+    targets: &[ImageFormat],
+    options: &ConversionOptions,
+) -> Result<Vec<Result<ConversionReport, ConverterError>>, ConverterError> {
+    check_input_size(input_path, options)?;
+    let source_format = source_format_of(input_path)?;
+    // The input must stay until the last output is written
+    let per_target = ConversionOptions { delete_original: false, ..options.clone() };
+
+    let mut reports: Vec<_> = if options.streaming {
+        // Streaming never holds a decoded image to share, so each target reads the file again
+        targets
+            .iter()
+            .map(|target| convert_file_streaming(input_path, source_format, target, &per_target))
+            .collect()
+    } else {
+        let input_bytes = fs::read(input_path)
+            .map_err(|e| ConverterError::read(input_path, e))?;
+        let input_size = input_bytes.len() as u64;
+        encode_many(&input_bytes, source_format, targets, options)
+            .map_err(|e| e.in_file(input_path))?
+            .into_iter()
+            .zip(targets)
+            .map(|(encoded, target)| {
+                let encoded = encoded.map_err(|e| e.in_file(input_path))?;
+                write_encoded(input_path, input_size, target, &per_target, encoded)
+            })
+            .collect()
+    };
+
+    let all_written = reports.iter().all(|report| report.as_ref().is_ok_and(|report| !report.skipped));
+    let any_in_place = targets.iter().any(|target| is_in_place(input_path, target, options));
+    if options.delete_original && all_written && !any_in_place {
+        let backup_path = output::retire(input_path, &options.backup)?;
+        for report in reports.iter_mut().flatten() {
+            report.backup_path = backup_path.clone();
+            report.original_removed = true;
+        }
+    }
+    Ok(reports)
+}
+
+/// Refuse files over `max_input_bytes` before reading them into memory
+fn check_input_size(input_path: &Path, options: &ConversionOptions) -> Result<(), ConverterError> {
+    if let Some(max) = options.limits.max_input_bytes {
+        let size = fs::metadata(input_path)
+            .map_err(|e| ConverterError::read(input_path, e))?
+            .len();
+        if size > max {
+            return Err(ConverterError::limit(LimitKind::InputBytes, max).in_file(input_path));
+        }
+    }
     Ok(())
 }
+
+fn source_format_of(input_path: &Path) -> Result<ImageFormat, ConverterError> {
+    ImageFormat::from_extension(input_path.to_str())
+        .ok_or_else(|| ConverterError::UnsupportedFormat("Cannot determine input format".to_string()))
+}
+
+/// Place and write the encoded output of one conversion, and report on it
+fn write_encoded(
+    input_path: &Path,
+    input_size: u64,
+    target_format: &ImageFormat,
+    options: &ConversionOptions,
+    encoded: EncodedOutput,
+) -> Result<ConversionReport, ConverterError> {
+    let EncodedOutput {
+        bytes: converted_bytes,
+        quality: chosen_quality,
+        ssim: achieved_ssim,
+        downscaled_to,
+        optimizer_savings,
+    } = encoded;
+
+    let dimensions = encoded_dimensions(&converted_bytes);
+    let (output_path, in_place) =
+        match place_output(input_path, target_format, options, dimensions, chosen_quality.or(options.quality))? {
+            Placement::Write { path, in_place } => (path, in_place),
+            Placement::Skip(planned_path) => {
+                return Ok(ConversionReport {
+                    chosen_quality,
+                    achieved_ssim,
+                    downscaled_to,
+                    ..skipped_report(planned_path, input_size)
+                });
+            }
+        };
+    let (backup_path, original_removed) = store_output(input_path, &output_path, target_format, in_place, options, |path| {
+        output::write_atomic(path, &converted_bytes)
+    })?;
+
+    Ok(ConversionReport {
+        output_path,
+        input_bytes: input_size,
+        output_bytes: converted_bytes.len() as u64,
+        skipped: false,
+        chosen_quality,
+        achieved_ssim,
+        downscaled_to,
+        optimizer_savings,
+        backup_path,
+        original_removed,
+    })
+}
+
+/// `convert_with_options` for `options.streaming`: the input is read and the output
+/// written row by row, so neither is ever held whole
+fn convert_file_streaming(
+    input_path: &Path,
+    source_format: ImageFormat,
+    target_format: &ImageFormat,
+    options: &ConversionOptions,
+) -> Result<ConversionReport, ConverterError> {
+    let open = || {
+        fs::File::open(input_path)
+            .map(BufReader::new)
+            .map_err(|e| ConverterError::read(input_path, e))
+    };
+    let input_size = fs::metadata(input_path)
+        .map_err(|e| ConverterError::read(input_path, e))?
+        .len();
+    let dimensions = streaming::output_dimensions(open()?, source_format, options)
+        .map_err(|e| e.in_file(input_path))?;
+
+    let (output_path, in_place) = match place_output(input_path, target_format, options, dimensions, options.quality)? {
+        Placement::Write { path, in_place } => (path, in_place),
+        Placement::Skip(planned_path) => return Ok(skipped_report(planned_path, input_size)),
+    };
+    let (backup_path, original_removed) = store_output(input_path, &output_path, target_format, in_place, options, |path| {
+        output::write_atomic_with(path, |file| {
+            streaming::convert(open()?, BufWriter::new(file), source_format, target_format, options)
+                .map(drop)
+                .map_err(|e| match e {
+                    // Write failures are about the output file, anything else about the input
+                    ConverterError::Io { path: None, stage: Stage::Write, source } => output::write_error(path, source),
+                    e => e.in_file(input_path),
+                })
+        })
+    })?;
+    let output_bytes = fs::metadata(&output_path)
+        .map_err(|e| ConverterError::read(&output_path, e))?
+        .len();
+
+    Ok(ConversionReport {
+        output_path,
+        input_bytes: input_size,
+        output_bytes,
+        skipped: false,
+        chosen_quality: None,
+        achieved_ssim: None,
+        downscaled_to: None,
+        optimizer_savings: None,
+        backup_path,
+        original_removed,
+    })
+}
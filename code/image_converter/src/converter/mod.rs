@@ -1,9 +1,26 @@
 pub mod errors;
 pub mod formats;
 pub mod main_converter;
+pub mod options;
+pub mod naming;
+pub mod output;
+pub mod pipeline;
+pub mod inspect;
+pub mod compare;
+pub mod streaming;
+pub mod responsive;
+pub mod text;
+pub mod thumbnails;
+pub mod atlas;
+pub mod watermark;
+pub mod filters;
+pub mod presets;
 
 pub mod jpeg_converter;
 pub mod png_converter;
 pub mod webp_converter;
 pub mod gif_converter;
-pub mod bmp_converter;
\ No newline at end of file
+pub mod bmp_converter;
+
+pub use inspect::inspect;
+pub use main_converter::{convert_bytes, convert_bytes_many, convert_many, convert_reader};
@@ -0,0 +1,137 @@
+use std::path::{Path, PathBuf};
+use chrono::Local;
+use crate::converter::errors::ConverterError;
+use crate::converter::formats::ImageFormat;
+use crate::converter::options::{CollisionPolicy, ConversionOptions};
+
+/// Template used when none is configured: the input stem with the new extension
+pub const DEFAULT_TEMPLATE: &str = "{stem}.{ext}";
+
+/// Values substituted into a file name template
+#[derive(Debug, Clone)]
+pub struct TemplateValues<'a> {
+    pub stem: &'a str,
+    pub format: ImageFormat,
+    pub width: u32,
+    pub height: u32,
+    /// Quality the output was encoded at, if it has one
+    pub quality: Option<u8>,
+}
+
+/// Fill in a file name template. Supported tokens:
+/// `{stem}`, `{ext}`, `{format}`, `{width}`, `{height}`, `{quality}`,
+/// `{date}` (YYYY-MM-DD), `{time}` (HHMMSS), `{year}`, `{month}`, `{day}`.
+/// `{quality}` is an error for an output without a quality.
+pub fn render_template(template: &str, values: &TemplateValues) -> Result<String, ConverterError> {
+    let now = Local::now();
+    let mut rendered = String::new();
+    let mut rest = template;
+
+    while let Some(start) = rest.find('{') {
+        rendered.push_str(&rest[..start]);
+        let end = rest[start..].find('}').ok_or_else(|| {
+            ConverterError::InvalidTemplate(format!("unclosed '{{' in \"{}\"", template))
+        })? + start;
+
+        let token = &rest[start + 1..end];
+        let value = match token {
+            "stem" => values.stem.to_string(),
+            "ext" => values.format.to_extension().to_string(),
+            "format" => format!("{:?}", values.format).to_lowercase(),
+            "width" => values.width.to_string(),
+            "height" => values.height.to_string(),
+            "quality" => match values.quality {
+                Some(quality) => quality.to_string(),
+                None => {
+                    return Err(ConverterError::InvalidTemplate(format!(
+                        "{{quality}} in \"{}\" needs a quality setting or search",
+                        template
+                    )));
+                }
+            },
+            "date" => now.format("%Y-%m-%d").to_string(),
+            "time" => now.format("%H%M%S").to_string(),
+            "year" => now.format("%Y").to_string(),
+            "month" => now.format("%m").to_string(),
+            "day" => now.format("%d").to_string(),
+            _ => {
+                return Err(ConverterError::InvalidTemplate(format!(
+                    "unknown token {{{}}} in \"{}\"",
+                    token, template
+                )));
+            }
+        };
+        rendered.push_str(&value);
+        rest = &rest[end + 1..];
+    }
+    rendered.push_str(rest);
+
+    if rendered.is_empty() || rendered.contains(['/', '\\']) {
+        return Err(ConverterError::InvalidTemplate(format!(
+            "\"{}\" does not produce a plain file name",
+            template
+        )));
+    }
+    Ok(rendered)
+}
+
+/// Where a conversion of `input_path` should be written, before collisions are considered.
+/// `quality` is the one the output was encoded at.
+pub fn planned_output_path(
+    input_path: &Path,
+    target_format: &ImageFormat,
+    options: &ConversionOptions,
+    dimensions: (u32, u32),
+    quality: Option<u8>,
+) -> Result<PathBuf, ConverterError> {
+    let stem = input_path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let template = options.name_template.as_deref().unwrap_or(DEFAULT_TEMPLATE);
+    let file_name = render_template(template, &TemplateValues {
+        stem: &stem,
+        format: *target_format,
+        width: dimensions.0,
+        height: dimensions.1,
+        quality,
+    })?;
+
+    let dir = match &options.output_dir {
+        Some(dir) => dir.clone(),
+        None => input_path.parent().map(Path::to_path_buf).unwrap_or_default(),
+    };
+    Ok(dir.join(file_name))
+}
+
+/// Apply the collision policy to a planned output path.
+/// Returns `None` when the job should be skipped.
+pub fn resolve_collision(path: PathBuf, policy: CollisionPolicy) -> Result<Option<PathBuf>, ConverterError> {
+    if !path.exists() {
+        return Ok(Some(path));
+    }
+    match policy {
+        CollisionPolicy::Overwrite => Ok(Some(path)),
+        CollisionPolicy::Skip => Ok(None),
+        CollisionPolicy::Fail => Err(ConverterError::OutputExists(path)),
+        CollisionPolicy::AutoSuffix => {
+            let stem = path
+                .file_stem()
+                .map(|stem| stem.to_string_lossy().to_string())
+                .unwrap_or_default();
+            let extension = path.extension().map(|ext| ext.to_string_lossy().to_string());
+            let candidate = |n: usize| {
+                let name = match &extension {
+                    Some(ext) => format!("{}_{}.{}", stem, n, ext),
+                    None => format!("{}_{}", stem, n),
+                };
+                path.with_file_name(name)
+            };
+            let free = (1..)
+                .map(candidate)
+                .find(|candidate| !candidate.exists())
+                .expect("some suffix is free");
+            Ok(Some(free))
+        }
+    }
+}
@@ -0,0 +1,304 @@
+use std::path::PathBuf;
+use crate::converter::watermark::Logo;
+
+/// What to do when the output file already exists
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CollisionPolicy {
+    #[default]
+    Overwrite,
+    /// Leave the existing file alone and report the job as skipped
+    Skip,
+    /// Append `_1`, `_2`, ... to the file stem until the name is free
+    AutoSuffix,
+    /// Return `ConverterError::OutputExists`
+    Fail,
+}
+
+impl CollisionPolicy {
+    pub const ALL: [CollisionPolicy; 4] = [
+        CollisionPolicy::Overwrite,
+        CollisionPolicy::Skip,
+        CollisionPolicy::AutoSuffix,
+        CollisionPolicy::Fail,
+    ];
+
+    /// The policy after this one, wrapping around
+    pub fn next(&self) -> Self {
+        let index = Self::ALL.iter().position(|policy| policy == self).unwrap_or(0);
+        Self::ALL[(index + 1) % Self::ALL.len()]
+    }
+}
+
+/// How WebP output is compressed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum WebpMode {
+    #[default]
+    Lossless,
+    /// VP8 compression at `ConversionOptions::quality` (needs the `lossy-webp` feature)
+    Lossy,
+    /// Lossless after preprocessing pixels, 0 (strongest) to 100 (off)
+    /// (needs the `lossy-webp` feature)
+    NearLossless(u8),
+}
+
+impl WebpMode {
+    /// The mode after this one, wrapping around
+    pub fn next(&self) -> Self {
+        match self {
+            WebpMode::Lossless => WebpMode::Lossy,
+            WebpMode::Lossy => WebpMode::NearLossless(60),
+            WebpMode::NearLossless(_) => WebpMode::Lossless,
+        }
+    }
+}
+
+/// WebP encoder settings beyond the shared quality
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WebpOptions {
+    pub mode: WebpMode,
+    /// Effort from 0 (fast) to 6 (smallest)
+    pub method: u8,
+    /// Quality of the alpha plane in lossy mode, 0-100
+    pub alpha_quality: u8,
+}
+
+impl Default for WebpOptions {
+    fn default() -> Self {
+        WebpOptions {
+            mode: WebpMode::Lossless,
+            method: 4,
+            alpha_quality: 100,
+        }
+    }
+}
+
+/// How JPEG stores the two color channels relative to brightness
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ChromaSubsampling {
+    /// Whatever the encoder picks
+    #[default]
+    Auto,
+    /// Full color resolution, for graphics and text
+    Yuv444,
+    /// Half horizontal color resolution
+    Yuv422,
+    /// Half color resolution both ways, for photos
+    Yuv420,
+}
+
+impl ChromaSubsampling {
+    pub const ALL: [ChromaSubsampling; 4] = [
+        ChromaSubsampling::Auto,
+        ChromaSubsampling::Yuv444,
+        ChromaSubsampling::Yuv422,
+        ChromaSubsampling::Yuv420,
+    ];
+
+    /// The subsampling after this one, wrapping around
+    pub fn next(&self) -> Self {
+        let index = Self::ALL.iter().position(|mode| mode == self).unwrap_or(0);
+        Self::ALL[(index + 1) % Self::ALL.len()]
+    }
+}
+
+/// JPEG encoder settings beyond the shared quality.
+/// Anything but the defaults needs the `mozjpeg` feature.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct JpegOptions {
+    pub progressive: bool,
+    /// Build Huffman tables for this image instead of using the standard ones
+    pub optimize_coding: bool,
+    pub subsampling: ChromaSubsampling,
+}
+
+impl WebpOptions {
+    pub fn is_lossy(&self) -> bool {
+        self.mode != WebpMode::Lossless
+    }
+}
+
+/// Where a copy of a file goes before it is replaced or removed
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum BackupPolicy {
+    #[default]
+    None,
+    /// Copy next to the file with this suffix appended, e.g. `photo.jpg.bak`
+    Suffix(String),
+    /// Copy into this directory, keeping the file name
+    TrashDir(PathBuf),
+}
+
+/// Bounds on what an input may decode to, for untrusted files. `None` means no bound,
+/// except that `image` keeps its own default allocation cap when `max_alloc` is unset.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct InputLimits {
+    pub max_width: Option<u32>,
+    pub max_height: Option<u32>,
+    /// Width times height
+    pub max_pixels: Option<u64>,
+    /// Largest single buffer the decoder may allocate, in bytes
+    pub max_alloc: Option<u64>,
+    /// Size of the encoded input
+    pub max_input_bytes: Option<u64>,
+    /// Frames of an animated GIF, WebP or PNG
+    pub max_frames: Option<usize>,
+}
+
+/// A color adjustment or filter. `ConversionOptions::filters` runs them in order.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Filter {
+    /// Added to every color channel, -255 to 255
+    Brightness(i32),
+    /// Percent more (or, when negative, less) contrast
+    Contrast(f32),
+    /// Above 1 brightens the midtones, below 1 darkens them
+    Gamma(f32),
+    /// Multiplies color intensity: 0 is gray, 1 unchanged
+    Saturation(f32),
+    /// Degrees around the color wheel
+    HueRotate(i32),
+    Grayscale,
+    Sepia,
+    Invert,
+    /// Stretch each channel to the full range
+    AutoLevels,
+    /// Gaussian blur with this standard deviation in pixels
+    Blur(f32),
+    /// Unsharp mask: blur radius, and the difference below which pixels are left alone
+    Sharpen { sigma: f32, threshold: i32 },
+}
+
+/// Side or corner of the image a watermark is placed against
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Anchor {
+    TopLeft,
+    Top,
+    TopRight,
+    Left,
+    Center,
+    Right,
+    BottomLeft,
+    Bottom,
+    #[default]
+    BottomRight,
+}
+
+impl Anchor {
+    pub const ALL: [Anchor; 9] = [
+        Anchor::TopLeft,
+        Anchor::Top,
+        Anchor::TopRight,
+        Anchor::Left,
+        Anchor::Center,
+        Anchor::Right,
+        Anchor::BottomLeft,
+        Anchor::Bottom,
+        Anchor::BottomRight,
+    ];
+
+    /// The anchor after this one, wrapping around
+    pub fn next(&self) -> Self {
+        let index = Self::ALL.iter().position(|anchor| anchor == self).unwrap_or(0);
+        Self::ALL[(index + 1) % Self::ALL.len()]
+    }
+}
+
+/// What a watermark shows
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WatermarkContent {
+    /// A logo image, decoded with `Logo::load`
+    Image(Logo),
+    /// Text in the built-in 8x8 font
+    Text(String),
+}
+
+/// Logo or text composited onto every output before it is encoded
+#[derive(Debug, Clone, PartialEq)]
+pub struct Watermark {
+    pub content: WatermarkContent,
+    pub anchor: Anchor,
+    /// Pixels between the watermark and the edges it is anchored to, and between tiles
+    pub margin: u32,
+    /// 0 (invisible) to 1 (opaque)
+    pub opacity: f32,
+    /// Width of the watermark as a fraction of the image's width
+    pub scale: f32,
+    /// Repeat the watermark across the whole image instead of placing it once
+    pub tile: bool,
+    /// Color of `WatermarkContent::Text`
+    pub text_color: [u8; 3],
+}
+
+impl Watermark {
+    /// `content` in the bottom-right corner, at half opacity and a quarter of the image's width
+    pub fn new(content: WatermarkContent) -> Self {
+        Watermark {
+            content,
+            anchor: Anchor::default(),
+            margin: 16,
+            opacity: 0.5,
+            scale: 0.25,
+            tile: false,
+            text_color: [255, 255, 255],
+        }
+    }
+}
+
+/// Settings for a single conversion. The defaults reproduce the plain
+/// `convert` behaviour: write next to the input with the extension swapped.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct ConversionOptions {
+    /// Directory to write into instead of the input's directory
+    pub output_dir: Option<PathBuf>,
+    /// Output file name, see `naming::render_template` for the tokens
+    pub name_template: Option<String>,
+    pub collision: CollisionPolicy,
+    /// Encoder quality (1-100) for lossy targets
+    pub quality: Option<u8>,
+    pub jpeg: JpegOptions,
+    pub webp: WebpOptions,
+    /// Search for the highest quality (and, if allowed, the largest size)
+    /// whose output fits in this many bytes
+    pub max_bytes: Option<u64>,
+    /// Let `max_bytes` shrink the image when even the lowest quality is too big
+    pub allow_downscale: bool,
+    /// Search for the lowest quality whose SSIM against the source is at least this.
+    /// Can't be combined with `max_bytes`.
+    pub min_ssim: Option<f64>,
+    /// Shrink the image to fit inside this width and height, keeping its aspect ratio
+    pub max_dimensions: Option<(u32, u32)>,
+    /// Adjustments applied in order after resizing
+    pub filters: Vec<Filter>,
+    /// Logo or text composited onto the image after the filters
+    pub watermark: Option<Watermark>,
+    /// Losslessly optimize PNG output at this oxipng preset (0-6)
+    pub png_optimization: Option<u8>,
+    pub limits: InputLimits,
+    /// Convert PNG and BMP band by band instead of decoding the whole image, for
+    /// files too large for memory; see `streaming` for what it supports
+    pub streaming: bool,
+    /// Run the encoders of a multi-target conversion on separate threads
+    pub parallel: bool,
+    /// Replace the input when converting to its own format,
+    /// ignoring `output_dir`, `name_template` and `collision`
+    pub in_place: bool,
+    /// Backup made of an input that is replaced in place or removed
+    pub backup: BackupPolicy,
+    /// Remove the input after a successful conversion to another format
+    pub delete_original: bool,
+}
+
+impl ConversionOptions {
+    /// Whether the output depends on encoder settings, so even a
+    /// same-format conversion has to decode and encode again
+    pub fn needs_reencode(&self) -> bool {
+        self.quality.is_some()
+            || self.max_bytes.is_some()
+            || self.min_ssim.is_some()
+            || self.max_dimensions.is_some()
+            || !self.filters.is_empty()
+            || self.watermark.is_some()
+            || self.jpeg != JpegOptions::default()
+            || self.webp != WebpOptions::default()
+    }
+}
@@ -0,0 +1,126 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use crate::converter::errors::ConverterError;
+use crate::converter::naming;
+use crate::converter::options::{BackupPolicy, CollisionPolicy};
+
+/// Distinguishes temp files created by concurrent writes within this process
+static TEMP_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+/// Write `bytes` to `path` so that readers see either the old file or the
+/// complete new one, never a truncated image. The data goes to a temp file in
+/// the same directory, is flushed to disk and then renamed over `path`.
+pub fn write_atomic(path: &Path, bytes: &[u8]) -> Result<(), ConverterError> {
+    write_atomic_with(path, |file| file.write_all(bytes).map_err(|e| write_error(path, e)))
+}
+
+/// Like `write_atomic`, but `fill` writes the contents into the temp file,
+/// so output that is produced piece by piece never has to be held whole
+pub fn write_atomic_with<F>(path: &Path, fill: F) -> Result<(), ConverterError>
+where
+    F: FnOnce(&mut File) -> Result<(), ConverterError>,
+{
+    let temp_path = temp_path_for(path);
+    let result = create_temp(&temp_path, path)
+        .map_err(|e| write_error(path, e))
+        .and_then(|mut file| {
+            fill(&mut file)?;
+            file.sync_all().map_err(|e| write_error(path, e))
+        })
+        .and_then(|()| fs::rename(&temp_path, path).map_err(|e| write_error(path, e)));
+
+    if result.is_err() {
+        let _ = fs::remove_file(&temp_path);
+        return result;
+    }
+
+    sync_parent_dir(path);
+    Ok(())
+}
+
+fn temp_path_for(path: &Path) -> PathBuf {
+    let name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let counter = TEMP_COUNTER.fetch_add(1, Ordering::Relaxed);
+    path.with_file_name(format!(".{}.{}-{}.tmp", name, process::id(), counter))
+}
+
+fn create_temp(temp_path: &Path, final_path: &Path) -> io::Result<File> {
+    let file = OpenOptions::new().write(true).create_new(true).open(temp_path)?;
+    // Keep the permissions of a file we are replacing
+    if let Ok(metadata) = fs::metadata(final_path) {
+        file.set_permissions(metadata.permissions())?;
+    }
+    Ok(file)
+}
+
+/// Persist the rename itself. Not every platform can open a directory, so this is best effort.
+fn sync_parent_dir(path: &Path) {
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    if let Ok(dir) = File::open(dir) {
+        let _ = dir.sync_all();
+    }
+}
+
+pub(crate) fn write_error(path: &Path, e: io::Error) -> ConverterError {
+    if matches!(e.kind(), ErrorKind::StorageFull | ErrorKind::QuotaExceeded) {
+        ConverterError::DiskFull(path.to_path_buf())
+    } else {
+        ConverterError::write(path, e)
+    }
+}
+
+/// Where the backup of `path` goes under `policy`, if anywhere
+fn backup_path(path: &Path, policy: &BackupPolicy) -> Result<Option<PathBuf>, ConverterError> {
+    let file_name = path.file_name().unwrap_or_default();
+    match policy {
+        BackupPolicy::None => Ok(None),
+        BackupPolicy::Suffix(suffix) => {
+            let mut name = file_name.to_os_string();
+            name.push(suffix);
+            Ok(Some(path.with_file_name(name)))
+        }
+        BackupPolicy::TrashDir(dir) => {
+            fs::create_dir_all(dir).map_err(|e| write_error(dir, e))?;
+            // Never clobber an earlier backup of a file with the same name
+            naming::resolve_collision(dir.join(file_name), CollisionPolicy::AutoSuffix)
+        }
+    }
+}
+
+/// Copy `path` to its backup location before it gets replaced
+pub fn back_up(path: &Path, policy: &BackupPolicy) -> Result<Option<PathBuf>, ConverterError> {
+    let Some(backup) = backup_path(path, policy)? else {
+        return Ok(None);
+    };
+    let bytes = fs::read(path)
+        .map_err(|e| ConverterError::read(path, e))?;
+    write_atomic(&backup, &bytes)?;
+    Ok(Some(backup))
+}
+
+/// Move `path` to its backup location, or delete it when no backup is wanted
+pub fn retire(path: &Path, policy: &BackupPolicy) -> Result<Option<PathBuf>, ConverterError> {
+    match backup_path(path, policy)? {
+        Some(backup) => {
+            // A rename cannot cross filesystems, so fall back to copy and delete
+            if fs::rename(path, &backup).is_err() {
+                back_up(path, policy)?;
+                fs::remove_file(path).map_err(|e| write_error(path, e))?;
+            }
+            Ok(Some(backup))
+        }
+        None => {
+            fs::remove_file(path).map_err(|e| write_error(path, e))?;
+            Ok(None)
+        }
+    }
+}
@@ -0,0 +1,177 @@
+use crate::converter::errors::{ConverterError, LimitKind, Stage};
+use crate::converter::inspect;
+use crate::converter::formats::ImageFormat;
+use crate::converter::options::{ConversionOptions, InputLimits, JpegOptions, WebpMode};
+use crate::converter::{filters, jpeg_converter, watermark, webp_converter};
+use image::imageops::FilterType;
+use image::{codecs::{jpeg::JpegEncoder, webp::WebPEncoder}, DynamicImage, ImageDecoder, ImageFormat as ImgFmt, ImageReader};
+use std::borrow::Cow;
+use std::io::Cursor;
+
+/// Check what can be learned without decoding pixels (input size, the header's
+/// dimensions and buffer size, and the number of frames) against `limits`
+pub fn check_limits(input: &[u8], limits: &InputLimits) -> Result<(), ConverterError> {
+    let exceeds = |value: u64, max: Option<u64>| max.is_some_and(|max| value > max);
+    if exceeds(input.len() as u64, limits.max_input_bytes) {
+        return Err(ConverterError::limit(LimitKind::InputBytes, limits.max_input_bytes.unwrap_or_default()));
+    }
+    if *limits == InputLimits::default() {
+        return Ok(());
+    }
+
+    let format = image::guess_format(input).map_err(ConverterError::decode)?;
+    let decoder = ImageReader::with_format(Cursor::new(input), format)
+        .into_decoder()
+        .map_err(ConverterError::decode)?;
+    let (width, height) = decoder.dimensions();
+    let checks = [
+        (LimitKind::Width, width as u64, limits.max_width.map(u64::from)),
+        (LimitKind::Height, height as u64, limits.max_height.map(u64::from)),
+        (LimitKind::Pixels, width as u64 * height as u64, limits.max_pixels),
+        (LimitKind::Allocation, decoder.total_bytes(), limits.max_alloc),
+    ];
+    if let Some((kind, _, max)) = checks.into_iter().find(|&(_, value, max)| exceeds(value, max)) {
+        return Err(ConverterError::limit(kind, max.unwrap_or_default()));
+    }
+
+    if let Some(max_frames) = limits.max_frames {
+        // Stop counting one past the limit, so a huge animation isn't walked to the end
+        let frames = inspect::frame_count(input, format, max_frames.saturating_add(1))?;
+        if frames > max_frames {
+            return Err(ConverterError::limit(LimitKind::Frames, max_frames as u64));
+        }
+    }
+    Ok(())
+}
+
+/// Size that fits `width` x `height` inside `max`, keeping the aspect ratio. Never enlarges.
+pub fn fit_dimensions(width: u32, height: u32, max: (u32, u32)) -> (u32, u32) {
+    let scale = (max.0 as f64 / width as f64).min(max.1 as f64 / height as f64);
+    if scale >= 1.0 {
+        return (width, height);
+    }
+    let side = |length: u32| ((length as f64 * scale).round() as u32).max(1);
+    (side(width), side(height))
+}
+
+/// Apply the transforms `options` asks for (resizing, the filters, then the watermark),
+/// borrowing `img` when there are none
+pub fn transform<'a>(img: &'a DynamicImage, options: &ConversionOptions) -> Result<Cow<'a, DynamicImage>, ConverterError> {
+    let mut transformed = match options.max_dimensions {
+        Some(max) => match fit_dimensions(img.width(), img.height(), max) {
+            (width, height) if (width, height) != (img.width(), img.height()) => {
+                Cow::Owned(img.resize_exact(width, height, FilterType::Lanczos3))
+            }
+            _ => Cow::Borrowed(img),
+        },
+        None => Cow::Borrowed(img),
+    };
+    if !options.filters.is_empty() {
+        transformed = Cow::Owned(filters::apply(&transformed, &options.filters));
+    }
+    match &options.watermark {
+        Some(mark) => Ok(Cow::Owned(watermark::apply(&transformed, mark)?)),
+        None => Ok(transformed),
+    }
+}
+
+/// Decode encoded image bytes, detecting the format from the contents.
+/// The header is checked against `limits` first, and the decoder itself is held to
+/// the dimension and allocation limits through `image::Limits`.
+pub fn decode(input: &[u8], limits: &InputLimits) -> Result<DynamicImage, ConverterError> {
+    check_limits(input, limits)?;
+
+    let mut image_limits = image::Limits::default();
+    image_limits.max_image_width = limits.max_width;
+    image_limits.max_image_height = limits.max_height;
+    if let Some(max_alloc) = limits.max_alloc {
+        image_limits.max_alloc = Some(max_alloc);
+    }
+
+    let mut reader = ImageReader::new(Cursor::new(input))
+        .with_guessed_format()
+        .map_err(|source| ConverterError::Io { path: None, stage: Stage::Decode, source })?;
+    reader.limits(image_limits);
+    reader.decode().map_err(|e| match e {
+        image::ImageError::Limits(_) => ConverterError::limit(
+            LimitKind::Allocation,
+            limits.max_alloc.or(image::Limits::default().max_alloc).unwrap_or_default(),
+        ),
+        e => ConverterError::decode(e),
+    })
+}
+
+/// `img` itself when JPEG can store it (8-bit gray or RGB), otherwise flattened to RGB8
+pub(crate) fn jpeg_compatible(img: &DynamicImage) -> Cow<'_, DynamicImage> {
+    match img {
+        DynamicImage::ImageLuma8(_) | DynamicImage::ImageRgb8(_) => Cow::Borrowed(img),
+        _ => Cow::Owned(DynamicImage::ImageRgb8(img.to_rgb8())), // JPEG doesn't support alpha
+    }
+}
+
+/// `img` itself when it has 8 bits per channel, otherwise narrowed to RGBA8
+fn eight_bit(img: &DynamicImage) -> Cow<'_, DynamicImage> {
+    match img {
+        DynamicImage::ImageLuma8(_)
+        | DynamicImage::ImageLumaA8(_)
+        | DynamicImage::ImageRgb8(_)
+        | DynamicImage::ImageRgba8(_) => Cow::Borrowed(img),
+        _ => Cow::Owned(DynamicImage::ImageRgba8(img.to_rgba8())),
+    }
+}
+
+/// Encode a decoded image in the target format, honouring the encoder settings in `options`.
+/// Pixels are only converted when the encoder can't take the decoded color type as it is.
+pub fn encode(
+    img: &DynamicImage,
+    target_format: &ImageFormat,
+    options: &ConversionOptions,
+) -> Result<Vec<u8>, ConverterError> {
+    let mut out_buf = Vec::new();
+    let (format, img) = match target_format {
+        ImageFormat::JPEG if options.jpeg != JpegOptions::default() => {
+            return jpeg_converter::encode_jpeg_advanced(img, options.quality.unwrap_or(75), &options.jpeg);
+        }
+        ImageFormat::JPEG => {
+            let img = jpeg_compatible(img);
+            let mut encoder = match options.quality {
+                Some(quality) => JpegEncoder::new_with_quality(&mut out_buf, quality.clamp(1, 100)),
+                None => JpegEncoder::new(&mut out_buf),
+            };
+            encoder.encode(img.as_bytes(), img.width(), img.height(), img.color().into())
+                .map_err(ConverterError::encode)?;
+            return Ok(out_buf);
+        }
+        ImageFormat::WEBP if options.webp.mode != WebpMode::Lossless => {
+            // Lossy encoders default to 75 like the JPEG encoder
+            return webp_converter::encode_webp_lossy(img, options.quality.unwrap_or(75), &options.webp);
+        }
+        ImageFormat::WEBP => {
+            let img = eight_bit(img);
+            WebPEncoder::new_lossless(&mut out_buf)
+                .encode(img.as_bytes(), img.width(), img.height(), img.color().into())
+                .map_err(ConverterError::encode)?;
+            return Ok(out_buf);
+        }
+        // PNG takes every color type the decoders produce, so keep the original one
+        ImageFormat::PNG => (ImgFmt::Png, Cow::Borrowed(img)),
+        ImageFormat::GIF => match img {
+            DynamicImage::ImageRgb8(_) | DynamicImage::ImageRgba8(_) => (ImgFmt::Gif, Cow::Borrowed(img)),
+            _ => (ImgFmt::Gif, Cow::Owned(DynamicImage::ImageRgba8(img.to_rgba8()))),
+        },
+        ImageFormat::BMP => (ImgFmt::Bmp, eight_bit(img)),
+    };
+    img.write_to(&mut Cursor::new(&mut out_buf), format)
+        .map_err(ConverterError::encode)?;
+    Ok(out_buf)
+}
+
+/// Decode and re-encode, the path every conversion goes through
+pub fn run(
+    input: &[u8],
+    target_format: &ImageFormat,
+    options: &ConversionOptions,
+) -> Result<Vec<u8>, ConverterError> {
+    let decoded = decode(input, &options.limits)?;
+    encode(&*transform(&decoded, options)?, target_format, options)
+}
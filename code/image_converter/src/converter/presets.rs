@@ -0,0 +1,75 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use crate::converter::errors::{ConverterError, Stage};
+use crate::converter::filters;
+use crate::converter::options::Filter;
+use crate::converter::output;
+
+/// Named filter chains, kept in a JSON file that maps each name to the chain
+/// in the form `filters::parse` reads, e.g. `{"faded": "contrast 20, saturation 1.2"}`
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct FilterPresets {
+    presets: BTreeMap<String, Vec<Filter>>,
+}
+
+impl FilterPresets {
+    /// The file presets are kept in when no other is given:
+    /// `filter_presets.json` in the user's config directory
+    pub fn default_path() -> Option<PathBuf> {
+        dirs::config_dir().map(|dir| dir.join("image_converter").join("filter_presets.json"))
+    }
+
+    /// Read the presets in `path`. A missing file holds no presets.
+    pub fn load(path: &Path) -> Result<Self, ConverterError> {
+        let json = match fs::read(path) {
+            Ok(json) => json,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(FilterPresets::default()),
+            Err(e) => return Err(ConverterError::read(path, e)),
+        };
+        let stored: BTreeMap<String, String> = serde_json::from_slice(&json)
+            .map_err(|e| ConverterError::failed(Stage::Read, e.to_string()).in_file(path))?;
+        let mut presets = BTreeMap::new();
+        for (name, chain) in stored {
+            let chain = filters::parse(&chain)
+                .map_err(|e| ConverterError::failed(Stage::Read, format!("preset \"{}\": {}", name, e)).in_file(path))?;
+            presets.insert(name, chain);
+        }
+        Ok(FilterPresets { presets })
+    }
+
+    /// Write the presets to `path`, creating its directory if needed
+    pub fn save(&self, path: &Path) -> Result<(), ConverterError> {
+        if let Some(parent) = path.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+            fs::create_dir_all(parent).map_err(|e| ConverterError::write(parent, e))?;
+        }
+        let stored: BTreeMap<&str, String> = self
+            .presets
+            .iter()
+            .map(|(name, chain)| (name.as_str(), filters::describe(chain)))
+            .collect();
+        let json = serde_json::to_string_pretty(&stored)
+            .map_err(|e| ConverterError::failed(Stage::Write, e.to_string()))?;
+        output::write_atomic(path, json.as_bytes())
+    }
+
+    pub fn get(&self, name: &str) -> Option<&[Filter]> {
+        self.presets.get(name).map(Vec::as_slice)
+    }
+
+    /// Store `chain` under `name`, replacing any preset of that name
+    pub fn insert(&mut self, name: &str, chain: Vec<Filter>) {
+        self.presets.insert(name.to_string(), chain);
+    }
+
+    /// Forget the preset `name`. Returns whether there was one.
+    pub fn remove(&mut self, name: &str) -> bool {
+        self.presets.remove(name).is_some()
+    }
+
+    /// Preset names and chains, sorted by name
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &[Filter])> {
+        self.presets.iter().map(|(name, chain)| (name.as_str(), chain.as_slice()))
+    }
+}
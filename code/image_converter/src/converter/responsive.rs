@@ -0,0 +1,201 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use serde::Serialize;
+use crate::converter::errors::{ConverterError, Stage};
+use crate::converter::formats::ImageFormat;
+use crate::converter::main_converter::{self, ConversionReport};
+use crate::converter::options::ConversionOptions;
+use crate::converter::output;
+
+/// Name given to each variant unless the options set a template of their own
+pub const RESPONSIVE_TEMPLATE: &str = "{stem}-{width}w.{ext}";
+
+/// What a responsive image set is made of
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ResponsiveSpec {
+    /// Widths to generate. Those wider than the source are capped at its width rather than upscaled.
+    pub widths: Vec<u32>,
+    /// Formats in order of preference. The last one is the `<img>` fallback,
+    /// the others become `<source>` elements.
+    pub formats: Vec<ImageFormat>,
+    /// The `sizes` attribute of the snippet
+    pub sizes: String,
+}
+
+impl Default for ResponsiveSpec {
+    fn default() -> Self {
+        ResponsiveSpec {
+            widths: vec![320, 640, 1280, 1920],
+            formats: vec![ImageFormat::WEBP, ImageFormat::JPEG],
+            sizes: "100vw".to_string(),
+        }
+    }
+}
+
+/// One generated file, as listed in the manifest
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ResponsiveVariant {
+    pub path: PathBuf,
+    /// MIME type, e.g. `image/webp`
+    pub format: String,
+    pub width: u32,
+    pub height: u32,
+    pub bytes: u64,
+}
+
+/// Result of `generate`
+#[derive(Debug, Clone, PartialEq)]
+pub struct ResponsiveSet {
+    pub variants: Vec<ResponsiveVariant>,
+    /// The `<picture>` snippet, also written to `html_path`
+    pub html: String,
+    pub html_path: PathBuf,
+    /// JSON list of `variants`
+    pub manifest_path: PathBuf,
+}
+
+/// Generate every width of `input_path` in every format of `spec`, then write a
+/// `<picture>` snippet and a JSON manifest next to them as `<stem>-responsive.html`
+/// and `<stem>-responsive.json`. The source is decoded once per width.
+/// A name template in `options` must contain `{width}`.
+pub fn generate(
+    input_path: &Path,
+    spec: &ResponsiveSpec,
+    options: &ConversionOptions,
+) -> Result<ResponsiveSet, ConverterError> {
+    if spec.formats.is_empty() {
+        return Err(ConverterError::UnsupportedFormat(
+            "A responsive set needs at least one format".to_string(),
+        ));
+    }
+    let template = options.name_template.as_deref().unwrap_or(RESPONSIVE_TEMPLATE);
+    if !template.contains("{width}") {
+        // Every width would be written to the same file
+        return Err(ConverterError::InvalidTemplate(format!(
+            "\"{}\" needs {{width}} to name each width of a responsive set",
+            template
+        )));
+    }
+    let (source_width, _) = image::image_dimensions(input_path)
+        .map_err(|e| ConverterError::decode(e).in_file(input_path))?;
+    let widths = widths_for(&spec.widths, source_width);
+
+    let mut variants = Vec::new();
+    for &width in &widths {
+        let variant_options = ConversionOptions {
+            max_dimensions: Some((width, u32::MAX)),
+            name_template: Some(template.to_string()),
+            in_place: false,
+            delete_original: false,
+            ..options.clone()
+        };
+        for (report, format) in main_converter::convert_many(input_path, &spec.formats, &variant_options)?
+            .into_iter()
+            .zip(&spec.formats)
+        {
+            variants.push(variant(report?, format)?);
+        }
+    }
+
+    let output_dir = options
+        .output_dir
+        .clone()
+        .unwrap_or_else(|| input_path.parent().map(Path::to_path_buf).unwrap_or_default());
+    let stem = input_path.file_stem().unwrap_or_default().to_string_lossy();
+    let html = picture_html(&variants, spec);
+    let html_path = output_dir.join(format!("{}-responsive.html", stem));
+    let manifest_path = output_dir.join(format!("{}-responsive.json", stem));
+
+    output::write_atomic(&html_path, html.as_bytes())?;
+    let manifest = serde_json::to_string_pretty(&variants)
+        .map_err(|e| ConverterError::failed(Stage::Write, e.to_string()))?;
+    output::write_atomic(&manifest_path, manifest.as_bytes())?;
+
+    Ok(ResponsiveSet { variants, html, html_path, manifest_path })
+}
+
+/// The requested widths that don't exceed the source, smallest first. Any wider
+/// request is served by a variant at the source's own width instead.
+fn widths_for(requested: &[u32], source_width: u32) -> Vec<u32> {
+    let mut widths: Vec<u32> = requested
+        .iter()
+        .map(|&width| width.min(source_width))
+        .filter(|&width| width > 0)
+        .collect();
+    widths.sort_unstable();
+    widths.dedup();
+    if widths.is_empty() {
+        widths.push(source_width);
+    }
+    widths
+}
+
+/// Manifest entry for a written output. An output the collision policy kept is described as it is on disk.
+fn variant(report: ConversionReport, format: &ImageFormat) -> Result<ResponsiveVariant, ConverterError> {
+    let path = report.output_path;
+    let (width, height) = image::image_dimensions(&path)
+        .map_err(|e| ConverterError::decode(e).in_file(&path))?;
+    let bytes = if report.skipped {
+        fs::metadata(&path).map_err(|e| ConverterError::read(&path, e))?.len()
+    } else {
+        report.output_bytes
+    };
+    Ok(ResponsiveVariant { path, format: format.mime_type().to_string(), width, height, bytes })
+}
+
+/// `<picture>` element offering `variants`: a `<source>` per preferred format and an
+/// `<img>` in the last. File names are relative, for a page next to the images.
+pub fn picture_html(variants: &[ResponsiveVariant], spec: &ResponsiveSpec) -> String {
+    let srcset = |format: &ImageFormat| {
+        variants
+            .iter()
+            .filter(|variant| variant.format == format.mime_type())
+            .map(|variant| format!("{} {}w", url(&variant.path), variant.width))
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+    let sizes = escape(&spec.sizes);
+
+    let mut html = String::from("<picture>\n");
+    let Some((fallback, preferred)) = spec.formats.split_last() else {
+        return html + "</picture>\n";
+    };
+    for format in preferred {
+        html.push_str(&format!(
+            "  <source type=\"{}\" srcset=\"{}\" sizes=\"{}\">\n",
+            format.mime_type(),
+            srcset(format),
+            sizes
+        ));
+    }
+    // The largest fallback is the plain `src`, and its size reserves the layout space
+    if let Some(largest) = variants
+        .iter()
+        .filter(|variant| variant.format == fallback.mime_type())
+        .max_by_key(|variant| variant.width)
+    {
+        html.push_str(&format!(
+            "  <img src=\"{}\" srcset=\"{}\" sizes=\"{}\" width=\"{}\" height=\"{}\" alt=\"\" loading=\"lazy\">\n",
+            url(&largest.path),
+            srcset(fallback),
+            sizes,
+            largest.width,
+            largest.height
+        ));
+    }
+    html.push_str("</picture>\n");
+    html
+}
+
+/// File name of `path` as it can appear in a `srcset`, where spaces and commas separate entries
+fn url(path: &Path) -> String {
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    escape(&name.replace('%', "%25").replace(' ', "%20").replace(',', "%2C"))
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('"', "&quot;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
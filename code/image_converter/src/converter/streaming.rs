@@ -0,0 +1,627 @@
+//! Band-by-band conversion for images too large to decode into one buffer.
+//! Rows are read from a PNG or uncompressed BMP, optionally shrunk with a box
+//! filter, and written straight into a PNG or BMP encoder, so memory use grows
+//! with the width of the image rather than its area.
+
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use crate::converter::errors::{ConverterError, LimitKind, Stage};
+use crate::converter::formats::ImageFormat;
+use crate::converter::options::{ConversionOptions, InputLimits};
+use crate::converter::pipeline;
+
+/// Channel layout of the 8-bit rows passed between readers and writers
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Layout {
+    L,
+    La,
+    Rgb,
+    Rgba,
+}
+
+impl Layout {
+    fn channels(self) -> usize {
+        match self {
+            Layout::L => 1,
+            Layout::La => 2,
+            Layout::Rgb => 3,
+            Layout::Rgba => 4,
+        }
+    }
+
+    fn has_alpha(self) -> bool {
+        matches!(self, Layout::La | Layout::Rgba)
+    }
+}
+
+/// Whether `convert` can read `source` and write `target`
+pub fn supports(source: ImageFormat, target: &ImageFormat) -> bool {
+    let streamable = |format: &ImageFormat| matches!(format, ImageFormat::PNG | ImageFormat::BMP);
+    streamable(&source) && streamable(target)
+}
+
+/// Width and height `convert` will produce for the image in `reader`, read from its header
+pub fn output_dimensions<R: Read + Seek>(
+    reader: R,
+    source_format: ImageFormat,
+    options: &ConversionOptions,
+) -> Result<(u32, u32), ConverterError> {
+    let rows = open_rows(reader, source_format, &options.limits)?;
+    Ok(fitted(rows.dimensions(), options))
+}
+
+/// Convert the image in `reader` to `target_format` one row at a time and write it to
+/// `writer`, which is handed back once flushed. Only PNG and BMP are handled, and of
+/// the options only `max_dimensions` and `limits` apply; shrinking uses a box filter.
+pub fn convert<R: Read + Seek, W: Write>(
+    mut reader: R,
+    mut writer: W,
+    source_format: ImageFormat,
+    target_format: &ImageFormat,
+    options: &ConversionOptions,
+) -> Result<W, ConverterError> {
+    if !supports(source_format, target_format) {
+        return Err(ConverterError::UnsupportedFormat(format!(
+            "Streaming conversion from {:?} to {:?} not supported, only PNG and BMP",
+            source_format, target_format
+        )));
+    }
+    if options.max_bytes.is_some() || options.min_ssim.is_some() || options.png_optimization.is_some() {
+        return Err(ConverterError::failed(
+            Stage::Encode,
+            "Size and SSIM targets and the PNG optimizer need the whole image, so they can't be streamed",
+        ));
+    }
+    if !options.filters.is_empty() || options.watermark.is_some() {
+        return Err(ConverterError::failed(
+            Stage::Transform,
+            "Filters and watermarks need the whole image, so they can't be streamed",
+        ));
+    }
+    if let Some(max) = options.limits.max_input_bytes {
+        let size = remaining_len(&mut reader).map_err(|source| read_error(Stage::Read, source))?;
+        if size > max {
+            return Err(ConverterError::limit(LimitKind::InputBytes, max));
+        }
+    }
+
+    let mut rows = open_rows(reader, source_format, &options.limits)?;
+    let layout = rows.layout();
+    let output = fitted(rows.dimensions(), options);
+    if output != rows.dimensions()
+        && let Some(max) = options.limits.max_alloc
+        && Shrink::allocation(rows.dimensions().0, output.0, layout) > max
+    {
+        return Err(ConverterError::limit(LimitKind::Allocation, max));
+    }
+    match target_format {
+        ImageFormat::PNG => {
+            let mut png = png_header(&mut writer, output, layout)?;
+            let stream = png.stream_writer().map_err(png_encode_error)?;
+            pump(rows.as_mut(), PngWriter { stream }, output)?;
+            png.finish().map_err(png_encode_error)?;
+        }
+        _ => pump(rows.as_mut(), BmpWriter::new(&mut writer, output, layout)?, output)?,
+    }
+
+    writer.flush().map_err(write_error)?;
+    Ok(writer)
+}
+
+/// Move every row from `rows` to `sink`, shrinking them to `output` on the way if needed
+fn pump<S: RowWriter>(rows: &mut dyn RowReader, mut sink: S, output: (u32, u32)) -> Result<(), ConverterError> {
+    let (width, height) = rows.dimensions();
+    let layout = rows.layout();
+    let mut row = vec![0; width as usize * layout.channels()];
+    if output == (width, height) {
+        for _ in 0..height {
+            rows.read_row(&mut row)?;
+            sink.write_row(&row)?;
+        }
+    } else {
+        let mut shrink = Shrink::new((width, height), output, layout);
+        for _ in 0..height {
+            rows.read_row(&mut row)?;
+            shrink.push(&row, &mut sink)?;
+        }
+    }
+    sink.finish()
+}
+
+fn fitted(dimensions: (u32, u32), options: &ConversionOptions) -> (u32, u32) {
+    match options.max_dimensions {
+        Some(max) => pipeline::fit_dimensions(dimensions.0, dimensions.1, max),
+        None => dimensions,
+    }
+}
+
+/// Header checks against `limits`, run by the readers before they allocate anything
+/// sized from the header. Only a row is ever held, so `max_alloc` bounds `row_bytes`, the
+/// largest row buffer (`convert` checks the shrink buffers once the output size is known);
+/// frames past the first are never read, so `max_frames` does not apply.
+fn check_limits(width: u32, height: u32, row_bytes: u64, limits: &InputLimits) -> Result<(), ConverterError> {
+    let exceeds = |value: u64, max: Option<u64>| max.is_some_and(|max| value > max);
+    let checks = [
+        (LimitKind::Width, width as u64, limits.max_width.map(u64::from)),
+        (LimitKind::Height, height as u64, limits.max_height.map(u64::from)),
+        (LimitKind::Pixels, width as u64 * height as u64, limits.max_pixels),
+        (LimitKind::Allocation, row_bytes, limits.max_alloc),
+    ];
+    match checks.into_iter().find(|&(_, value, max)| exceeds(value, max)) {
+        Some((kind, _, max)) => Err(ConverterError::limit(kind, max.unwrap_or_default())),
+        None => Ok(()),
+    }
+}
+
+fn remaining_len<S: Seek>(stream: &mut S) -> io::Result<u64> {
+    let start = stream.stream_position()?;
+    let end = stream.seek(SeekFrom::End(0))?;
+    stream.seek(SeekFrom::Start(start))?;
+    Ok(end.saturating_sub(start))
+}
+
+fn read_error(stage: Stage, source: io::Error) -> ConverterError {
+    ConverterError::Io { path: None, stage, source }
+}
+
+fn write_error(source: io::Error) -> ConverterError {
+    ConverterError::Io { path: None, stage: Stage::Write, source }
+}
+
+/// Source of 8-bit rows, top to bottom
+trait RowReader {
+    fn dimensions(&self) -> (u32, u32);
+    fn layout(&self) -> Layout;
+    fn read_row(&mut self, row: &mut [u8]) -> Result<(), ConverterError>;
+}
+
+/// Sink for 8-bit rows, top to bottom
+trait RowWriter {
+    fn write_row(&mut self, row: &[u8]) -> Result<(), ConverterError>;
+    fn finish(self) -> Result<(), ConverterError>
+    where
+        Self: Sized;
+}
+
+/// Reader for the rows of `reader`, once its header has passed `limits`
+fn open_rows<'a, R: Read + Seek + 'a>(
+    reader: R,
+    source_format: ImageFormat,
+    limits: &InputLimits,
+) -> Result<Box<dyn RowReader + 'a>, ConverterError> {
+    match source_format {
+        ImageFormat::PNG => Ok(Box::new(PngReader::new(reader, limits)?)),
+        ImageFormat::BMP => Ok(Box::new(BmpReader::new(reader, limits)?)),
+        _ => Err(ConverterError::UnsupportedFormat(format!(
+            "{:?} input can't be streamed, only PNG and BMP",
+            source_format
+        ))),
+    }
+}
+
+fn png_decode_error(e: png::DecodingError) -> ConverterError {
+    match e {
+        png::DecodingError::IoError(source) => read_error(Stage::Decode, source),
+        e => ConverterError::failed(Stage::Decode, e.to_string()),
+    }
+}
+
+fn png_encode_error(e: png::EncodingError) -> ConverterError {
+    match e {
+        png::EncodingError::IoError(source) => write_error(source),
+        e => ConverterError::failed(Stage::Encode, e.to_string()),
+    }
+}
+
+struct PngReader<R: Read> {
+    reader: png::Reader<R>,
+    layout: Layout,
+}
+
+impl<R: Read> PngReader<R> {
+    fn new(input: R, limits: &InputLimits) -> Result<Self, ConverterError> {
+        // The decoder holds its own row buffers to the allocation limit as well
+        let mut png_limits = png::Limits::default();
+        if let Some(max_alloc) = limits.max_alloc {
+            png_limits.bytes = usize::try_from(max_alloc).unwrap_or(usize::MAX);
+        }
+        let mut decoder = png::Decoder::new_with_limits(input, png_limits);
+        // Palettes, low bit depths and 16-bit samples all come out as 8-bit channels
+        decoder.set_transformations(png::Transformations::EXPAND | png::Transformations::STRIP_16);
+        let allocation_error = |e| match (e, limits.max_alloc) {
+            (png::DecodingError::LimitsExceeded, Some(max)) => ConverterError::limit(LimitKind::Allocation, max),
+            (e, _) => png_decode_error(e),
+        };
+
+        // Only the header is read here; `read_info` allocates the decoder's row buffer
+        let header = decoder.read_header_info().map_err(allocation_error)?;
+        let (width, height) = header.size();
+        let raw_row = header.raw_row_length() as u64;
+        check_limits(width, height, raw_row, limits)?;
+
+        let reader = decoder.read_info().map_err(allocation_error)?;
+        if reader.info().interlaced {
+            return Err(ConverterError::UnsupportedFormat(
+                "Interlaced PNG can't be streamed, its rows arrive in seven passes".to_string(),
+            ));
+        }
+        let layout = match reader.output_color_type().0 {
+            png::ColorType::Grayscale => Layout::L,
+            png::ColorType::GrayscaleAlpha => Layout::La,
+            png::ColorType::Rgb => Layout::Rgb,
+            _ => Layout::Rgba,
+        };
+        check_limits(width, height, width as u64 * layout.channels() as u64, limits)?;
+        Ok(PngReader { reader, layout })
+    }
+}
+
+impl<R: Read> RowReader for PngReader<R> {
+    fn dimensions(&self) -> (u32, u32) {
+        let info = self.reader.info();
+        (info.width, info.height)
+    }
+
+    fn layout(&self) -> Layout {
+        self.layout
+    }
+
+    fn read_row(&mut self, row: &mut [u8]) -> Result<(), ConverterError> {
+        let next = self.reader.next_row().map_err(png_decode_error)?;
+        let data = next.ok_or_else(|| ConverterError::failed(Stage::Decode, "PNG ended before its last row"))?;
+        row.copy_from_slice(data.data());
+        Ok(())
+    }
+}
+
+/// Write the PNG header; the rows then go through a `PngWriter` on its stream writer
+fn png_header<W: Write>(
+    output: W,
+    (width, height): (u32, u32),
+    layout: Layout,
+) -> Result<png::Writer<W>, ConverterError> {
+    let mut encoder = png::Encoder::new(output, width, height);
+    encoder.set_color(match layout {
+        Layout::L => png::ColorType::Grayscale,
+        Layout::La => png::ColorType::GrayscaleAlpha,
+        Layout::Rgb => png::ColorType::Rgb,
+        Layout::Rgba => png::ColorType::Rgba,
+    });
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.write_header().map_err(png_encode_error)
+}
+
+struct PngWriter<'a, W: Write> {
+    stream: png::StreamWriter<'a, W>,
+}
+
+impl<W: Write> RowWriter for PngWriter<'_, W> {
+    fn write_row(&mut self, row: &[u8]) -> Result<(), ConverterError> {
+        self.stream.write_all(row).map_err(write_error)
+    }
+
+    fn finish(self) -> Result<(), ConverterError> {
+        self.stream.finish().map_err(png_encode_error)
+    }
+}
+
+const BI_RGB: u32 = 0;
+const BI_BITFIELDS: u32 = 3;
+
+/// Reader for uncompressed 24- and 32-bit BMPs, the layouts large scans come in.
+/// Bottom-up files are read by seeking to each row.
+struct BmpReader<R: Read + Seek> {
+    input: R,
+    width: u32,
+    height: u32,
+    bottom_up: bool,
+    pixel_offset: u64,
+    stride: usize,
+    bytes_per_pixel: usize,
+    /// Byte index of red, green, blue and (if present) alpha within a pixel
+    offsets: [usize; 4],
+    layout: Layout,
+    next_row: u32,
+    raw: Vec<u8>,
+}
+
+fn le_u16(bytes: &[u8], at: usize) -> u16 {
+    u16::from_le_bytes([bytes[at], bytes[at + 1]])
+}
+
+fn le_u32(bytes: &[u8], at: usize) -> u32 {
+    u32::from_le_bytes([bytes[at], bytes[at + 1], bytes[at + 2], bytes[at + 3]])
+}
+
+impl<R: Read + Seek> BmpReader<R> {
+    fn new(mut input: R, limits: &InputLimits) -> Result<Self, ConverterError> {
+        let unsupported = |what: &str| ConverterError::UnsupportedFormat(format!(
+            "{} BMP can't be streamed, only uncompressed 24- and 32-bit",
+            what
+        ));
+        let corrupt = || ConverterError::failed(Stage::Decode, "BMP header is damaged");
+
+        // File header and info header, then room for the bitfield masks that follow it
+        let mut header = [0u8; 70];
+        input.read_exact(&mut header[..54]).map_err(|source| read_error(Stage::Decode, source))?;
+        if &header[..2] != b"BM" {
+            return Err(corrupt());
+        }
+        let pixel_offset = le_u32(&header, 10) as u64;
+        let info_size = le_u32(&header, 14);
+        if info_size < 40 {
+            return Err(unsupported("OS/2"));
+        }
+        let width = le_u32(&header, 18) as i32;
+        let height = le_u32(&header, 22) as i32;
+        let bits = le_u16(&header, 28);
+        let compression = le_u32(&header, 30);
+        if width <= 0 || height == 0 || height == i32::MIN {
+            return Err(corrupt());
+        }
+
+        let (offsets, layout) = match (bits, compression) {
+            (24, BI_RGB) => ([2, 1, 0, 0], Layout::Rgb),
+            // The fourth byte of BI_RGB pixels is padding
+            (32, BI_RGB) => ([2, 1, 0, 0], Layout::Rgb),
+            (32, BI_BITFIELDS) => {
+                // Only the V3 header and later carry an alpha mask
+                let mask_bytes = if info_size >= 56 { 16 } else { 12 };
+                input
+                    .read_exact(&mut header[54..54 + mask_bytes])
+                    .map_err(|source| read_error(Stage::Decode, source))?;
+                let byte_of = |mask: u32| match mask {
+                    0x0000_00ff => Some(0),
+                    0x0000_ff00 => Some(1),
+                    0x00ff_0000 => Some(2),
+                    0xff00_0000 => Some(3),
+                    _ => None,
+                };
+                let masks = [le_u32(&header, 54), le_u32(&header, 58), le_u32(&header, 62)];
+                let [Some(r), Some(g), Some(b)] = masks.map(byte_of) else {
+                    return Err(unsupported("Bitfield"));
+                };
+                let alpha = (info_size >= 56).then(|| le_u32(&header, 66)).filter(|&mask| mask != 0);
+                match alpha.map(byte_of) {
+                    Some(Some(a)) => ([r, g, b, a], Layout::Rgba),
+                    Some(None) => return Err(unsupported("Bitfield")),
+                    None => ([r, g, b, 0], Layout::Rgb),
+                }
+            }
+            (_, BI_RGB | BI_BITFIELDS) => return Err(unsupported(&format!("{}-bit", bits))),
+            _ => return Err(unsupported("Compressed")),
+        };
+
+        let bytes_per_pixel = bits as usize / 8;
+        let stride = (width as usize * bytes_per_pixel).div_ceil(4) * 4;
+        // Both the raw row and the converted row are sized from the header
+        let row_bytes = stride.max(width as usize * layout.channels()) as u64;
+        check_limits(width as u32, height.unsigned_abs(), row_bytes, limits)?;
+        Ok(BmpReader {
+            input,
+            width: width as u32,
+            height: height.unsigned_abs(),
+            bottom_up: height > 0,
+            pixel_offset,
+            stride,
+            bytes_per_pixel,
+            offsets,
+            layout,
+            next_row: 0,
+            raw: vec![0; stride],
+        })
+    }
+}
+
+impl<R: Read + Seek> RowReader for BmpReader<R> {
+    fn dimensions(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    fn layout(&self) -> Layout {
+        self.layout
+    }
+
+    fn read_row(&mut self, row: &mut [u8]) -> Result<(), ConverterError> {
+        let stored = if self.bottom_up {
+            self.height - 1 - self.next_row
+        } else {
+            self.next_row
+        };
+        self.input
+            .seek(SeekFrom::Start(self.pixel_offset + stored as u64 * self.stride as u64))
+            .and_then(|_| self.input.read_exact(&mut self.raw))
+            .map_err(|source| read_error(Stage::Decode, source))?;
+        self.next_row += 1;
+
+        let channels = self.layout.channels();
+        for (pixel, out) in self.raw.chunks_exact(self.bytes_per_pixel).zip(row.chunks_exact_mut(channels)) {
+            for (channel, &offset) in out.iter_mut().zip(&self.offsets) {
+                *channel = pixel[offset];
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Writes top-down BMPs: 24-bit for opaque rows and 32-bit with an alpha bitfield otherwise.
+/// Gray rows are widened to color, since BMP has no gray layout without a palette.
+struct BmpWriter<W: Write> {
+    output: W,
+    layout: Layout,
+    raw: Vec<u8>,
+}
+
+impl<W: Write> BmpWriter<W> {
+    fn new(mut output: W, (width, height): (u32, u32), layout: Layout) -> Result<Self, ConverterError> {
+        let alpha = layout.has_alpha();
+        let (bytes_per_pixel, info_size) = if alpha { (4u32, 108u32) } else { (3, 40) };
+        let stride = (width as u64 * bytes_per_pixel as u64).div_ceil(4) * 4;
+        let pixel_offset = 14 + info_size;
+        let file_size = u32::try_from(pixel_offset as u64 + stride * height as u64)
+            .map_err(|_| ConverterError::failed(Stage::Encode, "Image is too large for a BMP file"))?;
+        let height = i32::try_from(height)
+            .map_err(|_| ConverterError::failed(Stage::Encode, "Image is too tall for a BMP file"))?;
+
+        let mut header = Vec::with_capacity(pixel_offset as usize);
+        header.extend_from_slice(b"BM");
+        header.extend_from_slice(&file_size.to_le_bytes());
+        header.extend_from_slice(&[0; 4]);
+        header.extend_from_slice(&pixel_offset.to_le_bytes());
+        header.extend_from_slice(&info_size.to_le_bytes());
+        header.extend_from_slice(&(width as i32).to_le_bytes());
+        // A negative height stores the rows top-down, in the order they arrive
+        header.extend_from_slice(&(-height).to_le_bytes());
+        header.extend_from_slice(&1u16.to_le_bytes());
+        header.extend_from_slice(&(bytes_per_pixel as u16 * 8).to_le_bytes());
+        header.extend_from_slice(&(if alpha { BI_BITFIELDS } else { BI_RGB }).to_le_bytes());
+        header.extend_from_slice(&((stride * height as u64) as u32).to_le_bytes());
+        // 72 DPI, no palette
+        header.extend_from_slice(&2835u32.to_le_bytes());
+        header.extend_from_slice(&2835u32.to_le_bytes());
+        header.extend_from_slice(&[0; 8]);
+        if alpha {
+            for mask in [0x00ff_0000u32, 0x0000_ff00, 0x0000_00ff, 0xff00_0000] {
+                header.extend_from_slice(&mask.to_le_bytes());
+            }
+            header.extend_from_slice(b"BGRs");
+            // Endpoints and gamma, unused for sRGB
+            header.extend_from_slice(&[0; 48]);
+        }
+        output.write_all(&header).map_err(write_error)?;
+
+        Ok(BmpWriter { output, layout, raw: vec![0; stride as usize] })
+    }
+}
+
+impl<W: Write> RowWriter for BmpWriter<W> {
+    fn write_row(&mut self, row: &[u8]) -> Result<(), ConverterError> {
+        let bytes_per_pixel = if self.layout.has_alpha() { 4 } else { 3 };
+        for (pixel, out) in row.chunks_exact(self.layout.channels()).zip(self.raw.chunks_exact_mut(bytes_per_pixel)) {
+            let (r, g, b, a) = match *pixel {
+                [l] => (l, l, l, 255),
+                [l, a] => (l, l, l, a),
+                [r, g, b] => (r, g, b, 255),
+                [r, g, b, a] => (r, g, b, a),
+                _ => unreachable!("layouts have one to four channels"),
+            };
+            out[..3].copy_from_slice(&[b, g, r]);
+            if bytes_per_pixel == 4 {
+                out[3] = a;
+            }
+        }
+        self.output.write_all(&self.raw).map_err(write_error)
+    }
+
+    fn finish(self) -> Result<(), ConverterError> {
+        Ok(())
+    }
+}
+
+/// Box filter that averages each block of source pixels into one output pixel, taking
+/// source rows one at a time and passing each output row on as soon as it is complete.
+/// Color is weighted by alpha so transparent pixels don't bleed into their neighbours.
+struct Shrink {
+    layout: Layout,
+    source_height: u32,
+    output_height: u32,
+    /// Output column of each source column
+    columns: Vec<usize>,
+    /// Per output pixel: channel sums, then the number of source pixels added
+    sums: Vec<u64>,
+    counts: Vec<u64>,
+    current_row: u32,
+    rows_seen: u32,
+    output: Vec<u8>,
+}
+
+impl Shrink {
+    /// Bytes held while shrinking rows `source_width` wide to `output_width`: the source
+    /// row, the column map, the accumulators and the output row
+    fn allocation(source_width: u32, output_width: u32, layout: Layout) -> u64 {
+        let (source, output, channels) = (source_width as u64, output_width as u64, layout.channels() as u64);
+        let row = source * channels;
+        let columns = source * size_of::<usize>() as u64;
+        let accumulators = output * (channels + 1) * size_of::<u64>() as u64;
+        row + columns + accumulators + output * channels
+    }
+
+    fn new(source: (u32, u32), output: (u32, u32), layout: Layout) -> Self {
+        let columns = (0..source.0 as u64)
+            .map(|x| (x * output.0 as u64 / source.0 as u64) as usize)
+            .collect();
+        let channels = layout.channels();
+        Shrink {
+            layout,
+            source_height: source.1,
+            output_height: output.1,
+            columns,
+            sums: vec![0; output.0 as usize * channels],
+            counts: vec![0; output.0 as usize],
+            current_row: 0,
+            rows_seen: 0,
+            output: vec![0; output.0 as usize * channels],
+        }
+    }
+
+    fn push<S: RowWriter>(&mut self, row: &[u8], sink: &mut S) -> Result<(), ConverterError> {
+        let target_row = (self.rows_seen as u64 * self.output_height as u64 / self.source_height as u64) as u32;
+        if target_row != self.current_row {
+            self.emit(sink)?;
+            self.current_row = target_row;
+        }
+
+        let channels = self.layout.channels();
+        let alpha = self.layout.has_alpha();
+        for (pixel, &column) in row.chunks_exact(channels).zip(&self.columns) {
+            let sums = &mut self.sums[column * channels..(column + 1) * channels];
+            let (weight, color_channels) = if alpha {
+                (pixel[channels - 1] as u64, channels - 1)
+            } else {
+                (1, channels)
+            };
+            for (sum, &value) in sums[..color_channels].iter_mut().zip(pixel) {
+                *sum += value as u64 * weight;
+            }
+            if alpha {
+                sums[channels - 1] += weight;
+            }
+            self.counts[column] += 1;
+        }
+
+        self.rows_seen += 1;
+        if self.rows_seen == self.source_height {
+            self.emit(sink)?;
+        }
+        Ok(())
+    }
+
+    fn emit<S: RowWriter>(&mut self, sink: &mut S) -> Result<(), ConverterError> {
+        let channels = self.layout.channels();
+        let alpha = self.layout.has_alpha();
+        for ((out, sums), &count) in self
+            .output
+            .chunks_exact_mut(channels)
+            .zip(self.sums.chunks_exact(channels))
+            .zip(&self.counts)
+        {
+            let count = count.max(1);
+            let (color_divisor, color_channels) = if alpha {
+                (sums[channels - 1], channels - 1)
+            } else {
+                (count, channels)
+            };
+            for (value, &sum) in out[..color_channels].iter_mut().zip(sums) {
+                *value = match color_divisor {
+                    0 => 0,
+                    divisor => ((sum + divisor / 2) / divisor) as u8,
+                };
+            }
+            if alpha {
+                out[channels - 1] = ((sums[channels - 1] + count / 2) / count) as u8;
+            }
+        }
+        self.sums.fill(0);
+        self.counts.fill(0);
+        sink.write_row(&self.output)
+    }
+}
@@ -0,0 +1,59 @@
+use font8x8::legacy::{BASIC_LEGACY, LATIN_LEGACY};
+use image::{Rgba, RgbaImage};
+
+/// Side of a glyph of the built-in font, before scaling
+pub const GLYPH_SIZE: u32 = 8;
+
+/// Width of `text` drawn at `scale`
+pub fn text_width(text: &str, scale: u32) -> u32 {
+    text.chars().count() as u32 * GLYPH_SIZE * scale
+}
+
+/// The 8x8 bitmap of `c`: one byte per row, lowest bit leftmost.
+/// Characters outside ASCII and Latin-1 draw as '?'.
+fn glyph(c: char) -> [u8; 8] {
+    match c as u32 {
+        code @ 0..=0x7f => BASIC_LEGACY[code as usize],
+        code @ 0xa0..=0xff => LATIN_LEGACY[code as usize - 0xa0],
+        _ => BASIC_LEGACY['?' as usize],
+    }
+}
+
+/// Draw `text` with its top-left corner at `(x, y)`, each font pixel a `scale`-sized
+/// square blended over the image by `color`'s alpha. Whatever falls outside is clipped.
+pub fn draw_text(img: &mut RgbaImage, text: &str, x: i64, y: i64, scale: u32, color: Rgba<u8>) {
+    let scale = scale.max(1) as i64;
+    let step = GLYPH_SIZE as i64 * scale;
+    for (index, c) in text.chars().enumerate() {
+        let left = x + index as i64 * step;
+        for (row, bits) in glyph(c).iter().enumerate() {
+            for column in (0..GLYPH_SIZE as i64).filter(|column| bits & (1 << column) != 0) {
+                fill_square(img, left + column * scale, y + row as i64 * scale, scale, color);
+            }
+        }
+    }
+}
+
+fn fill_square(img: &mut RgbaImage, x: i64, y: i64, side: i64, color: Rgba<u8>) {
+    let (width, height) = (img.width() as i64, img.height() as i64);
+    for py in y.max(0)..(y + side).min(height) {
+        for px in x.max(0)..(x + side).min(width) {
+            blend(img.get_pixel_mut(px as u32, py as u32), color);
+        }
+    }
+}
+
+/// Source-over blend of `top` onto `pixel`
+pub(crate) fn blend(pixel: &mut Rgba<u8>, top: Rgba<u8>) {
+    let alpha = top[3] as u32;
+    if alpha == 0 {
+        return;
+    }
+    let under = pixel[3] as u32 * (255 - alpha) / 255;
+    let out_alpha = alpha + under;
+    for channel in 0..3 {
+        let mixed = top[channel] as u32 * alpha + pixel[channel] as u32 * under;
+        pixel[channel] = ((mixed + out_alpha / 2) / out_alpha) as u8;
+    }
+    pixel[3] = out_alpha as u8;
+}
@@ -0,0 +1,161 @@
+use std::path::{Path, PathBuf};
+use image::{imageops, DynamicImage, Rgba, RgbaImage};
+use crate::converter::errors::{ConverterError, Stage};
+use crate::converter::formats::{self, ImageFormat};
+use crate::converter::main_converter;
+use crate::converter::options::ConversionOptions;
+use crate::converter::{output, pipeline, text};
+
+/// Name given to each thumbnail unless the options set a template of their own
+pub const THUMBNAIL_TEMPLATE: &str = "{stem}-thumb.{ext}";
+
+/// Name of the contact sheet, without the extension
+pub const SHEET_STEM: &str = "contact-sheet";
+
+/// Thumbnail size and contact sheet layout
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ThumbnailSpec {
+    /// Box every thumbnail is shrunk to fit, keeping its aspect ratio
+    pub size: (u32, u32),
+    pub format: ImageFormat,
+    /// Thumbnails per row of the contact sheet
+    pub columns: u32,
+    /// Space around and between the cells of the sheet
+    pub padding: u32,
+    /// Write each file name under its thumbnail
+    pub captions: bool,
+    pub background: Rgba<u8>,
+    pub sheet_format: ImageFormat,
+}
+
+impl Default for ThumbnailSpec {
+    fn default() -> Self {
+        ThumbnailSpec {
+            size: (160, 160),
+            format: ImageFormat::JPEG,
+            columns: 6,
+            padding: 8,
+            captions: true,
+            background: Rgba([255, 255, 255, 255]),
+            sheet_format: ImageFormat::PNG,
+        }
+    }
+}
+
+/// Result of `generate`
+#[derive(Debug)]
+pub struct ThumbnailReport {
+    /// Thumbnail of each image that converted, in file name order
+    pub thumbnails: Vec<PathBuf>,
+    pub sheet_path: PathBuf,
+    /// Images that could not be thumbnailed; they are left off the sheet
+    pub failed: Vec<(PathBuf, ConverterError)>,
+}
+
+/// Thumbnail every image in `dir` (not its subdirectories) and lay the thumbnails out
+/// on one contact sheet. Everything goes to `options.output_dir`, or a `thumbnails`
+/// directory inside `dir`.
+pub fn generate(
+    dir: &Path,
+    spec: &ThumbnailSpec,
+    options: &ConversionOptions,
+) -> Result<ThumbnailReport, ConverterError> {
+    let output_dir = options.output_dir.clone().unwrap_or_else(|| dir.join("thumbnails"));
+    let sheet_path = output_dir.join(format!("{}.{}", SHEET_STEM, spec.sheet_format.to_extension()));
+    let inputs: Vec<PathBuf> = formats::read_image_dir(dir)
+        .map_err(|e| ConverterError::read(dir, e))?
+        .into_iter()
+        .map(|entry| entry.path())
+        .filter(|path| path.is_file() && *path != sheet_path)
+        .collect();
+    if inputs.is_empty() {
+        return Err(ConverterError::failed(Stage::Read, "No images to thumbnail").in_file(dir));
+    }
+
+    let thumbnail_options = ConversionOptions {
+        output_dir: Some(output_dir),
+        name_template: options.name_template.clone().or_else(|| Some(THUMBNAIL_TEMPLATE.to_string())),
+        max_dimensions: Some(spec.size),
+        in_place: false,
+        delete_original: false,
+        ..options.clone()
+    };
+    let mut thumbnails = Vec::new();
+    let mut failed = Vec::new();
+    for input in &inputs {
+        let mut input_options = thumbnail_options.clone();
+        // `photo.png` and `photo.bmp` would both become `photo-thumb.jpg`
+        if options.name_template.is_none() && shares_stem(input, &inputs) {
+            let extension = input.extension().unwrap_or_default().to_string_lossy().to_lowercase();
+            input_options.name_template = Some(format!("{{stem}}-{}-thumb.{{ext}}", extension));
+        }
+        let input = input.clone();
+        match main_converter::convert_with_options(&input, &spec.format, &input_options) {
+            // A skipped thumbnail already exists, so it still goes on the sheet
+            Ok(report) => thumbnails.push((input, report.output_path)),
+            Err(e) => failed.push((input, e)),
+        }
+    }
+
+    let sheet = contact_sheet(&thumbnails, spec)?;
+    let bytes = pipeline::encode(&DynamicImage::ImageRgba8(sheet), &spec.sheet_format, options)?;
+    output::write_atomic(&sheet_path, &bytes)?;
+
+    Ok(ThumbnailReport {
+        thumbnails: thumbnails.into_iter().map(|(_, thumbnail)| thumbnail).collect(),
+        sheet_path,
+        failed,
+    })
+}
+
+fn shares_stem(path: &Path, paths: &[PathBuf]) -> bool {
+    paths.iter().any(|other| other != path && other.file_stem() == path.file_stem())
+}
+
+/// Caption text color and the gap between a thumbnail and its caption
+const CAPTION_COLOR: Rgba<u8> = Rgba([48, 48, 48, 255]);
+const CAPTION_GAP: u32 = 4;
+
+/// Grid of `(source, thumbnail)` pairs, each thumbnail centered in a cell of `spec.size`
+fn contact_sheet(thumbnails: &[(PathBuf, PathBuf)], spec: &ThumbnailSpec) -> Result<RgbaImage, ConverterError> {
+    let count = thumbnails.len().max(1) as u32;
+    let columns = spec.columns.clamp(1, count);
+    let rows = count.div_ceil(columns);
+    let caption_height = if spec.captions { CAPTION_GAP + text::GLYPH_SIZE } else { 0 };
+    let (cell_width, cell_height) = (spec.size.0, spec.size.1 + caption_height);
+
+    let mut sheet = RgbaImage::from_pixel(
+        columns * cell_width + (columns + 1) * spec.padding,
+        rows * cell_height + (rows + 1) * spec.padding,
+        spec.background,
+    );
+    for (index, (source, thumbnail)) in thumbnails.iter().enumerate() {
+        let (column, row) = (index as u32 % columns, index as u32 / columns);
+        let left = spec.padding + column * (cell_width + spec.padding);
+        let top = spec.padding + row * (cell_height + spec.padding);
+
+        let img = image::open(thumbnail).map_err(|e| ConverterError::decode(e).in_file(thumbnail))?;
+        let x = left + cell_width.saturating_sub(img.width()) / 2;
+        let y = top + spec.size.1.saturating_sub(img.height()) / 2;
+        imageops::overlay(&mut sheet, &img.to_rgba8(), x as i64, y as i64);
+
+        if spec.captions {
+            let name = source.file_name().unwrap_or_default().to_string_lossy();
+            let caption = fit_caption(&name, cell_width);
+            let x = left + cell_width.saturating_sub(text::text_width(&caption, 1)) / 2;
+            let y = top + spec.size.1 + CAPTION_GAP;
+            text::draw_text(&mut sheet, &caption, x as i64, y as i64, 1, CAPTION_COLOR);
+        }
+    }
+    Ok(sheet)
+}
+
+/// `name` shortened with "..." to fit `width` pixels of the built-in font
+fn fit_caption(name: &str, width: u32) -> String {
+    let fits = (width / text::GLYPH_SIZE) as usize;
+    if name.chars().count() <= fits {
+        return name.to_string();
+    }
+    let kept: String = name.chars().take(fits.saturating_sub(3)).collect();
+    format!("{}...", kept)
+}
@@ -0,0 +1,115 @@
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use image::imageops::{self, FilterType};
+use image::{DynamicImage, Rgba, RgbaImage};
+use crate::converter::errors::ConverterError;
+use crate::converter::options::{Anchor, InputLimits, Watermark, WatermarkContent};
+use crate::converter::{pipeline, text};
+
+/// A logo decoded once when the watermark is set up, then shared by every
+/// image the options are used for
+#[derive(Clone)]
+pub struct Logo {
+    path: PathBuf,
+    image: Arc<DynamicImage>,
+}
+
+impl Logo {
+    /// Decode the logo at `path`, held to `limits` like any input
+    pub fn load(path: &Path, limits: &InputLimits) -> Result<Self, ConverterError> {
+        let bytes = fs::read(path).map_err(|e| ConverterError::read(path, e))?;
+        let image = pipeline::decode(&bytes, limits).map_err(|e| e.in_file(path))?;
+        Ok(Logo { path: path.to_path_buf(), image: Arc::new(image) })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl fmt::Debug for Logo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Logo({}, {}x{})", self.path.display(), self.image.width(), self.image.height())
+    }
+}
+
+/// Two logos are the same when they share one decode
+impl PartialEq for Logo {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.image, &other.image)
+    }
+}
+
+impl Eq for Logo {}
+
+/// Composite `watermark` onto `img`. An image without alpha stays without alpha;
+/// anything deeper than 8 bits per channel comes back as 8 bits.
+pub fn apply(img: &DynamicImage, watermark: &Watermark) -> Result<DynamicImage, ConverterError> {
+    let Some(mark) = render(watermark, img.width())? else {
+        return Ok(img.clone());
+    };
+    let mut canvas = img.to_rgba8();
+    let (width, height) = canvas.dimensions();
+
+    if watermark.tile {
+        let step_x = (mark.width() + watermark.margin) as usize;
+        let step_y = (mark.height() + watermark.margin) as usize;
+        for y in (watermark.margin..height).step_by(step_y) {
+            for x in (watermark.margin..width).step_by(step_x) {
+                imageops::overlay(&mut canvas, &mark, x as i64, y as i64);
+            }
+        }
+    } else {
+        let (x, y) = position(watermark.anchor, (width, height), mark.dimensions(), watermark.margin);
+        imageops::overlay(&mut canvas, &mark, x, y);
+    }
+
+    if img.color().has_alpha() {
+        Ok(DynamicImage::ImageRgba8(canvas))
+    } else {
+        Ok(DynamicImage::ImageRgb8(DynamicImage::ImageRgba8(canvas).to_rgb8()))
+    }
+}
+
+/// The watermark at its final size and opacity for an image `image_width` wide,
+/// or `None` when there is nothing to draw
+fn render(watermark: &Watermark, image_width: u32) -> Result<Option<RgbaImage>, ConverterError> {
+    let target_width = ((image_width as f32 * watermark.scale).round() as u32).max(1);
+    let mut mark = match &watermark.content {
+        WatermarkContent::Image(logo) => {
+            let logo = &logo.image;
+            let height = (logo.height() as u64 * target_width as u64 / logo.width().max(1) as u64).max(1);
+            logo.resize_exact(target_width, height as u32, FilterType::Lanczos3).to_rgba8()
+        }
+        WatermarkContent::Text(label) if label.is_empty() => return Ok(None),
+        WatermarkContent::Text(label) => {
+            // Whole multiples of the font keep the glyphs crisp
+            let scale = (target_width / text::text_width(label, 1)).max(1);
+            let mut mark = RgbaImage::new(text::text_width(label, scale), text::GLYPH_SIZE * scale);
+            let [r, g, b] = watermark.text_color;
+            text::draw_text(&mut mark, label, 0, 0, scale, Rgba([r, g, b, 255]));
+            mark
+        }
+    };
+    let opacity = watermark.opacity.clamp(0.0, 1.0);
+    for pixel in mark.pixels_mut() {
+        pixel[3] = (pixel[3] as f32 * opacity).round() as u8;
+    }
+    Ok(Some(mark))
+}
+
+/// Top-left corner of a `mark`-sized watermark in an `image`-sized canvas
+fn position(anchor: Anchor, image: (u32, u32), mark: (u32, u32), margin: u32) -> (i64, i64) {
+    let place = |image: u32, mark: u32, start: bool, end: bool| match (start, end) {
+        (true, _) => margin as i64,
+        (_, true) => image as i64 - mark as i64 - margin as i64,
+        _ => (image as i64 - mark as i64) / 2,
+    };
+    let left = matches!(anchor, Anchor::TopLeft | Anchor::Left | Anchor::BottomLeft);
+    let right = matches!(anchor, Anchor::TopRight | Anchor::Right | Anchor::BottomRight);
+    let top = matches!(anchor, Anchor::TopLeft | Anchor::Top | Anchor::TopRight);
+    let bottom = matches!(anchor, Anchor::BottomLeft | Anchor::Bottom | Anchor::BottomRight);
+    (place(image.0, mark.0, left, right), place(image.1, mark.1, top, bottom))
+}
@@ -1,56 +1,65 @@
 use crate::converter::errors::ConverterError;
-use image::{DynamicImage, ImageFormat as ImgFmt};
-use std::io::Cursor;
+use crate::converter::formats::ImageFormat;
+use crate::converter::options::{ConversionOptions, WebpOptions};
+use crate::converter::pipeline;
+use image::DynamicImage;
 
 /// Convert WebP to PNG format
-pub fn convert_webp_to_png(input: Vec<u8>) -> Result<Vec<u8>, ConverterError> {
-    // Decode WebP using the image crate (supports both lossless and lossy WebP)
-    let dyn_img: DynamicImage = image::load_from_memory(&input)
-        .map_err(|e| ConverterError::ConversionError(e.to_string()))?;
-    let rgba = dyn_img.to_rgba8();
-    let (w, h) = (rgba.width(), rgba.height());
-    let mut out_buf = Vec::new();
-    DynamicImage::ImageRgba8(image::ImageBuffer::from_raw(w, h, rgba.into_raw()).unwrap())
-        .write_to(&mut Cursor::new(&mut out_buf), ImgFmt::Png)
-        .map_err(|e| ConverterError::WriteError(e.to_string()))?;
-    Ok(out_buf)
+pub fn convert_webp_to_png(input: &[u8]) -> Result<Vec<u8>, ConverterError> {
+    pipeline::run(input, &ImageFormat::PNG, &ConversionOptions::default())
 }
 
 /// Convert WebP to JPEG format
-pub fn convert_webp_to_jpeg(input: Vec<u8>) -> Result<Vec<u8>, ConverterError> {
-    let dyn_img: DynamicImage = image::load_from_memory(&input)
-        .map_err(|e| ConverterError::ConversionError(e.to_string()))?;
-    let rgb = dyn_img.to_rgb8(); // JPEG doesn't support alpha
-    let (w, h) = (rgb.width(), rgb.height());
-    let mut out_buf = Vec::new();
-    DynamicImage::ImageRgb8(image::ImageBuffer::from_raw(w, h, rgb.into_raw()).unwrap())
-        .write_to(&mut Cursor::new(&mut out_buf), ImgFmt::Jpeg)
-        .map_err(|e| ConverterError::WriteError(e.to_string()))?;
-    Ok(out_buf)
+pub fn convert_webp_to_jpeg(input: &[u8]) -> Result<Vec<u8>, ConverterError> {
+    pipeline::run(input, &ImageFormat::JPEG, &ConversionOptions::default())
 }
 
 /// Convert WebP to GIF format
-pub fn convert_webp_to_gif(input: Vec<u8>) -> Result<Vec<u8>, ConverterError> {
-    let dyn_img: DynamicImage = image::load_from_memory(&input)
-        .map_err(|e| ConverterError::ConversionError(e.to_string()))?;
-    let rgba = dyn_img.to_rgba8();
-    let (w, h) = (rgba.width(), rgba.height());
-    let mut out_buf = Vec::new();
-    DynamicImage::ImageRgba8(image::ImageBuffer::from_raw(w, h, rgba.into_raw()).unwrap())
-        .write_to(&mut Cursor::new(&mut out_buf), ImgFmt::Gif)
-        .map_err(|e| ConverterError::WriteError(e.to_string()))?;
-    Ok(out_buf)
+pub fn convert_webp_to_gif(input: &[u8]) -> Result<Vec<u8>, ConverterError> {
+    pipeline::run(input, &ImageFormat::GIF, &ConversionOptions::default())
 }
 
 /// Convert WebP to BMP format
-pub fn convert_webp_to_bmp(input: Vec<u8>) -> Result<Vec<u8>, ConverterError> {
-    let dyn_img: DynamicImage = image::load_from_memory(&input)
-        .map_err(|e| ConverterError::ConversionError(e.to_string()))?;
-    let rgba = dyn_img.to_rgba8();
-    let (w, h) = (rgba.width(), rgba.height());
-    let mut out_buf = Vec::new();
-    DynamicImage::ImageRgba8(image::ImageBuffer::from_raw(w, h, rgba.into_raw()).unwrap())
-        .write_to(&mut Cursor::new(&mut out_buf), ImgFmt::Bmp)
-        .map_err(|e| ConverterError::WriteError(e.to_string()))?;
-    Ok(out_buf)
+pub fn convert_webp_to_bmp(input: &[u8]) -> Result<Vec<u8>, ConverterError> {
+    pipeline::run(input, &ImageFormat::BMP, &ConversionOptions::default())
+}
+
+/// Encode an image as lossy or near-lossless WebP through libwebp
+#[cfg(feature = "lossy-webp")]
+pub fn encode_webp_lossy(img: &DynamicImage, quality: u8, options: &WebpOptions) -> Result<Vec<u8>, ConverterError> {
+    use crate::converter::errors::Stage;
+
+    let mut config = webp::WebPConfig::new()
+        .map_err(|_| ConverterError::failed(Stage::Encode, "libwebp version mismatch"))?;
+    config.quality = quality.clamp(1, 100) as f32;
+    config.method = options.method.min(6) as i32;
+    config.alpha_quality = options.alpha_quality.min(100) as i32;
+    if let crate::converter::options::WebpMode::NearLossless(level) = options.mode {
+        // Near-lossless is a preprocessing step of the lossless encoder
+        config.lossless = 1;
+        config.near_lossless = level.min(100) as i32;
+    }
+    // libwebp reads RGB and RGBA directly, anything else is converted once
+    let converted;
+    let encoder = match img {
+        DynamicImage::ImageRgb8(rgb) => webp::Encoder::from_rgb(rgb.as_raw(), rgb.width(), rgb.height()),
+        DynamicImage::ImageRgba8(rgba) => webp::Encoder::from_rgba(rgba.as_raw(), rgba.width(), rgba.height()),
+        _ => {
+            converted = img.to_rgba8();
+            webp::Encoder::from_rgba(converted.as_raw(), converted.width(), converted.height())
+        }
+    };
+    let encoded = encoder
+        .encode_advanced(&config)
+        .map_err(|e| ConverterError::failed(Stage::Encode, format!("libwebp: {:?}", e)))?;
+    Ok(encoded.to_vec())
+}
+
+/// Without libwebp only lossless WebP can be written
+#[cfg(not(feature = "lossy-webp"))]
+pub fn encode_webp_lossy(_img: &DynamicImage, _quality: u8, options: &WebpOptions) -> Result<Vec<u8>, ConverterError> {
+    Err(ConverterError::UnsupportedFormat(format!(
+        "{:?} WebP needs the lossy-webp feature",
+        options.mode
+    )))
 }
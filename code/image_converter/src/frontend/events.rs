@@ -1,8 +1,15 @@
 use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use std::collections::BTreeSet;
 use std::fs;
-use std::path::PathBuf;
-use crate::converter::formats::ImageFormat;
-use crate::converter::main_converter;
+use std::ops::RangeInclusive;
+use std::path::{Path, PathBuf};
+use crate::converter::formats::{self, ImageFormat};
+use crate::converter::options::ConversionOptions;
+use crate::frontend::preview::{ComparisonPreview, PreviewState};
+use crate::converter::responsive::ResponsiveSpec;
+use crate::converter::thumbnails::ThumbnailSpec;
+use crate::frontend::queue::{ConversionJob, ConversionQueue, JobKind, JobStatus};
+use crate::frontend::settings::OptionField;
 
 #[derive(Debug)]
 pub enum AppEvent {
@@ -14,6 +21,9 @@ pub enum AppEvent {
 pub enum AppMode {
     SelectMode,
     ConvertMode,
+    QueueMode,
+    CompareMode,
+    OptionsMode,
 }
 
 #[derive(Debug)]
@@ -22,11 +32,20 @@ pub struct AppState {
     pub entries: Vec<fs::DirEntry>,
     pub selected_index: usize,
     pub selected_file: Option<PathBuf>,
+    pub marked: BTreeSet<PathBuf>,
+    pub visual_anchor: Option<usize>,
     pub mode: AppMode,
     pub selected_format_index: usize,
-    pub to_convert: Vec<(PathBuf, ImageFormat)>,
+    /// Formats checked in the format list, in `ImageFormat::ALL` order
+    pub checked_formats: Vec<ImageFormat>,
+    pub queue: ConversionQueue,
+    pub options: ConversionOptions,
+    pub selected_option_index: usize,
+    /// Text of the option being edited, if any
+    pub editing: Option<String>,
+    pub preview: PreviewState,
+    pub comparison: Option<ComparisonPreview>,
     pub status_message: Option<String>,
-    pub command_buffer: String,
 }
 
 impl AppState {
@@ -36,46 +55,27 @@ impl AppState {
             entries: Vec::new(),
             selected_index: 0,
             selected_file: None,
+            marked: BTreeSet::new(),
+            visual_anchor: None,
             mode: AppMode::SelectMode,
             selected_format_index: 0,
-            to_convert: Vec::new(),
+            checked_formats: Vec::new(),
+            queue: ConversionQueue::default(),
+            options: ConversionOptions::default(),
+            selected_option_index: 0,
+            editing: None,
+            preview: PreviewState::default(),
+            comparison: None,
             status_message: None,
-            command_buffer: String::new(),
         };
         app.refresh_entries();
         app
     }
 
     pub fn refresh_entries(&mut self) {
-        match fs::read_dir(&self.cwd) {
-            Ok(dir_entries) => {
-                self.entries = dir_entries
-                    .filter_map(|entry| entry.ok())
-                    .filter(|entry| {
-                        if let Ok(metadata) = entry.metadata() {
-                            if metadata.is_dir() {
-                                return true;
-                            }
-                            // Check if file extension matches ImageFormat
-                            if let Some(filename) = entry.file_name().to_str() {
-                                return ImageFormat::from_extension(Some(filename)).is_some();
-                            }
-                        }
-                        false
-                    })
-                    .collect();
-
-                // Sort entries: directories first, then files, both alphabetically
-                self.entries.sort_by(|a, b| {
-                    let a_is_dir = a.metadata().map(|m| m.is_dir()).unwrap_or(false);
-                    let b_is_dir = b.metadata().map(|m| m.is_dir()).unwrap_or(false);
-                    
-                    match (a_is_dir, b_is_dir) {
-                        (true, false) => std::cmp::Ordering::Less,
-                        (false, true) => std::cmp::Ordering::Greater,
-                        _ => a.file_name().cmp(&b.file_name()),
-                    }
-                });
+        match formats::read_image_dir(&self.cwd) {
+            Ok(entries) => {
+                self.entries = entries;
 
                 if self.selected_index >= self.entries.len() {
                     self.selected_index = self.entries.len().saturating_sub(1);
@@ -101,6 +101,7 @@ impl AppState {
             self.cwd = parent.to_path_buf();
             self.refresh_entries();
             self.selected_index = 0;
+            self.visual_anchor = None;
         }
     }
 
@@ -117,6 +118,7 @@ impl AppState {
                 self.cwd = selected_entry.path();
                 self.refresh_entries();
                 self.selected_index = 0;
+                self.visual_anchor = None;
             } else {
                 // Select file
                 self.selected_file = Some(selected_entry.path());
@@ -136,41 +138,244 @@ impl AppState {
         }
     }
 
+    /// Number of rows the ".." entry takes up above the directory entries
+    fn entry_offset(&self) -> usize {
+        if self.can_go_up() { 1 } else { 0 }
+    }
+
+    /// Image file shown at the given row of the directory pane
+    fn image_at(&self, row: usize) -> Option<PathBuf> {
+        let entry = self.entries.get(row.checked_sub(self.entry_offset())?)?;
+        match entry.metadata() {
+            Ok(metadata) if metadata.is_file() => Some(entry.path()),
+            _ => None,
+        }
+    }
+
+    /// Image file under the cursor in the directory pane
+    pub fn highlighted_image(&self) -> Option<PathBuf> {
+        self.image_at(self.selected_index)
+    }
+
+    fn image_paths(&self) -> Vec<PathBuf> {
+        (0..self.entries.len() + self.entry_offset())
+            .filter_map(|row| self.image_at(row))
+            .collect()
+    }
+
+    /// Rows covered by the visual selection, if one is in progress
+    pub fn visual_range(&self) -> Option<RangeInclusive<usize>> {
+        self.visual_anchor.map(|anchor| {
+            anchor.min(self.selected_index)..=anchor.max(self.selected_index)
+        })
+    }
+
+    pub fn is_marked(&self, row: usize, path: &Path) -> bool {
+        self.marked.contains(path)
+            || self.visual_range().is_some_and(|range| range.contains(&row))
+    }
+
+    pub fn toggle_mark(&mut self) {
+        if let Some(path) = self.image_at(self.selected_index)
+            && !self.marked.remove(&path)
+        {
+            self.marked.insert(path);
+        }
+    }
+
+    pub fn invert_marks(&mut self) {
+        for path in self.image_paths() {
+            if !self.marked.remove(&path) {
+                self.marked.insert(path);
+            }
+        }
+    }
+
+    pub fn mark_all(&mut self) {
+        self.marked.extend(self.image_paths());
+    }
+
+    /// Start a visual selection at the cursor, or mark every image it covers
+    pub fn toggle_visual(&mut self) {
+        match self.visual_range() {
+            Some(range) => {
+                let paths: Vec<PathBuf> = range.filter_map(|row| self.image_at(row)).collect();
+                self.marked.extend(paths);
+                self.visual_anchor = None;
+            }
+            None => self.visual_anchor = Some(self.selected_index),
+        }
+    }
+
     pub fn move_format_up(&mut self) {
         self.selected_format_index = self.selected_format_index.saturating_sub(1);
     }
 
     pub fn move_format_down(&mut self) {
-        if self.selected_format_index < 5 {
+        if self.selected_format_index + 1 < ImageFormat::ALL.len() {
             self.selected_format_index += 1;
         }
     }
 
+    /// Check or uncheck the highlighted format as one of several targets
+    pub fn toggle_format_check(&mut self) {
+        let format = ImageFormat::ALL[self.selected_format_index];
+        if self.checked_formats.contains(&format) {
+            self.checked_formats.retain(|checked| *checked != format);
+        } else {
+            self.checked_formats.push(format);
+            self.checked_formats
+                .sort_by_key(|checked| ImageFormat::ALL.iter().position(|f| f == checked));
+        }
+    }
+
+    /// The checked formats, or the highlighted one when none are checked
+    pub fn target_formats(&self) -> Vec<ImageFormat> {
+        if self.checked_formats.is_empty() {
+            vec![ImageFormat::ALL[self.selected_format_index]]
+        } else {
+            self.checked_formats.clone()
+        }
+    }
+
+    /// Queue one job per target format for the marked files, or the selected file
     pub fn confirm_conversion(&mut self) {
-        if let Some(ref file_path) = self.selected_file {
-            let formats = [
-                ImageFormat::PNG,
-                ImageFormat::JPEG,
-                ImageFormat::WEBP,
-                ImageFormat::GIF,
-                ImageFormat::BMP,
-            ];
-            
-            let selected_format = formats[self.selected_format_index].clone();
-            self.to_convert.push((file_path.clone(), selected_format.clone()));
-            
+        let targets = self.target_formats();
+        if !self.marked.is_empty() {
+            let count = self.marked.len();
+            for path in std::mem::take(&mut self.marked) {
+                self.queue_targets(path, &targets);
+            }
+
+            self.status_message = Some(format!(
+                "Queued {} marked files → {:?} (press r to start)",
+                count,
+                targets
+            ));
+        } else if let Some(file_path) = self.selected_file.clone() {
+            self.queue_targets(file_path.clone(), &targets);
+
             self.status_message = Some(format!(
-                "Will convert {} → {}",
+                "Queued {} → {:?} (press r to start)",
                 file_path.display(),
-                format!("{:?}", selected_format).to_uppercase()
+                targets
             ));
+        }
+    }
+
+    /// Queue one job converting `path` to `targets`; several targets share a
+    /// job so the file is decoded once
+    fn queue_targets(&mut self, path: PathBuf, targets: &[ImageFormat]) {
+        match targets {
+            [target] => self.queue.push(path, *target, self.options.clone()),
+            _ => self.queue.push_many(path, targets.to_vec(), self.options.clone()),
+        }
+    }
+
+    /// Queue a responsive set for the marked files, or the selected file, in the
+    /// checked formats (or the default WebP and JPEG when none are checked)
+    pub fn queue_responsive(&mut self) {
+        let mut spec = ResponsiveSpec::default();
+        if !self.checked_formats.is_empty() {
+            spec.formats = self.checked_formats.clone();
+        }
+        let inputs: Vec<PathBuf> = if self.marked.is_empty() {
+            self.selected_file.iter().cloned().collect()
+        } else {
+            std::mem::take(&mut self.marked).into_iter().collect()
+        };
+        for input in &inputs {
+            self.queue.push_responsive(input.clone(), spec.clone(), self.options.clone());
+        }
+        if !inputs.is_empty() {
+            self.status_message = Some(format!(
+                "Queued responsive sets for {} files, {:?} at {:?} wide (press r to start)",
+                inputs.len(),
+                spec.formats,
+                spec.widths
+            ));
+        }
+    }
+
+    /// Queue thumbnails and a contact sheet of the images in the current directory
+    pub fn queue_contact_sheet(&mut self) {
+        self.queue.push_contact_sheet(self.cwd.clone(), ThumbnailSpec::default(), self.options.clone());
+        self.status_message = Some(format!(
+            "Queued a contact sheet of {} (press r to start)",
+            self.cwd.display()
+        ));
+    }
+
+    pub fn selected_option(&self) -> OptionField {
+        OptionField::ALL[self.selected_option_index]
+    }
 
-            main_converter::convert(
-                &file_path,
-                &selected_format,
-            ).unwrap_or_else(|e| {
-                self.status_message = Some(format!("Conversion error: {}", e));
-            });
+    pub fn move_option_up(&mut self) {
+        self.selected_option_index = self.selected_option_index.saturating_sub(1);
+    }
+
+    pub fn move_option_down(&mut self) {
+        if self.selected_option_index + 1 < OptionField::ALL.len() {
+            self.selected_option_index += 1;
+        }
+    }
+
+    /// Start editing the selected option, or cycle it if it isn't text
+    pub fn activate_option(&mut self) {
+        let field = self.selected_option();
+        if field.is_text() {
+            self.editing = Some(field.edit_text(&self.options));
+        } else {
+            field.cycle(&mut self.options);
+        }
+    }
+
+    pub fn commit_option(&mut self) {
+        if let Some(text) = self.editing.take() {
+            let field = self.selected_option();
+            match field.apply_text(&mut self.options, &text) {
+                Ok(()) => self.status_message = Some(format!("{} updated", field.label())),
+                Err(e) => self.status_message = Some(e),
+            }
+        }
+    }
+
+    pub fn run_queue(&mut self) {
+        if self.queue.start() {
+            self.status_message = Some("Running conversion queue...".to_string());
+        } else {
+            self.status_message = Some("No pending jobs in the queue".to_string());
+        }
+    }
+
+    /// Open the compare view for the selected job, once it has been converted
+    pub fn compare_selected_job(&mut self) {
+        match self.queue.jobs.get(self.queue.selected_index) {
+            Some(ConversionJob {
+                status: JobStatus::Done,
+                kind: JobKind::Convert(_),
+                input_path,
+                output_path: Some(output_path),
+                ..
+            }) => {
+                self.comparison = Some(self.preview.comparison(input_path, output_path));
+                self.mode = AppMode::CompareMode;
+            }
+            _ => {
+                self.status_message = Some("Only finished single-file jobs can be compared".to_string());
+            }
+        }
+    }
+
+    pub fn on_tick(&mut self) {
+        if self.queue.step() {
+            self.status_message = Some(format!(
+                "Queue finished: {} done, {} skipped, {} failed",
+                self.queue.count(&JobStatus::Done),
+                self.queue.count(&JobStatus::Skipped),
+                self.queue.count(&JobStatus::Failed(String::new()))
+            ));
+            self.preview.invalidate();
             self.refresh_entries();
         }
     }
@@ -182,76 +387,73 @@ pub fn handle_input(app: &mut AppState, key: KeyEvent) -> bool {
         return true; // Signal to quit
     }
 
+    if app.editing.is_some() {
+        handle_edit_input(app, key);
+        return false;
+    }
+
     match key.code {
+        // Thumbnail the current directory onto a contact sheet. Shift+T, so a
+        // stray keypress doesn't thumbnail a whole directory.
+        KeyCode::Char('T') if app.mode == AppMode::SelectMode => app.queue_contact_sheet(),
         KeyCode::Char(c) => {
             let ch = c.to_lowercase().next().unwrap_or(c);
             match ch {
-                'k' => {
-                    app.command_buffer.clear();
-                    match app.mode {
-                        AppMode::SelectMode => app.move_up(),
-                        AppMode::ConvertMode => app.move_format_up(),
-                    }
-                }
-                'j' => {
-                    app.command_buffer.clear();
-                    match app.mode {
-                        AppMode::SelectMode => app.move_down(),
-                        AppMode::ConvertMode => app.move_format_down(),
-                    }
-                }
+                'k' => match app.mode {
+                    AppMode::SelectMode => app.move_up(),
+                    AppMode::ConvertMode => app.move_format_up(),
+                    AppMode::QueueMode => app.queue.move_up(),
+                    AppMode::OptionsMode => app.move_option_up(),
+                    AppMode::CompareMode => {}
+                },
+                'j' => match app.mode {
+                    AppMode::SelectMode => app.move_down(),
+                    AppMode::ConvertMode => app.move_format_down(),
+                    AppMode::QueueMode => app.queue.move_down(),
+                    AppMode::OptionsMode => app.move_option_down(),
+                    AppMode::CompareMode => {}
+                },
                 _ => {
-                    app.command_buffer.push(ch);
-
-                    // Check for complete commands
-                    let buffer_lower = app.command_buffer.to_lowercase();
-
-                    // Quit commands
-                    if buffer_lower == "q" || buffer_lower == "quit" {
-                        return true; // Signal to quit
-                    }
-
-                    // Select mode commands
-                    if buffer_lower == "s" || buffer_lower == "sel" || 
-                       buffer_lower == "select" || buffer_lower == "selection" {
-                        app.mode = AppMode::SelectMode;
-                        app.command_buffer.clear();
+                    if handle_mode_key(app, ch) {
                         return false;
                     }
-
-                    // Convert mode commands
-                    if buffer_lower == "c" || buffer_lower == "conv" || 
-                       buffer_lower == "convert" || buffer_lower == "conversion" {
-                        if app.selected_file.is_some() {
+                    match ch {
+                        // Quit
+                        'q' => return true,
+                        's' => app.mode = AppMode::SelectMode,
+                        'c' if app.selected_file.is_some() || !app.marked.is_empty() => {
                             app.mode = AppMode::ConvertMode;
                         }
-                        app.command_buffer.clear();
-                        return false;
-                    }
-
-                    // Clear buffer if it gets too long without matching
-                    if app.command_buffer.len() > 10 {
-                        app.command_buffer.clear();
+                        'l' => app.mode = AppMode::QueueMode,
+                        'o' => app.mode = AppMode::OptionsMode,
+                        // Run every pending job
+                        'r' => app.run_queue(),
+                        _ => {}
                     }
                 }
             }
         }
         KeyCode::Up => {
-            app.command_buffer.clear();
             match app.mode {
                 AppMode::SelectMode => app.move_up(),
                 AppMode::ConvertMode => app.move_format_up(),
+                AppMode::QueueMode if key.modifiers.contains(KeyModifiers::SHIFT) => app.queue.shift_up(),
+                AppMode::QueueMode => app.queue.move_up(),
+                AppMode::OptionsMode => app.move_option_up(),
+                AppMode::CompareMode => {}
             }
         }
         KeyCode::Down => {
-            app.command_buffer.clear();
             match app.mode {
                 AppMode::SelectMode => app.move_down(),
                 AppMode::ConvertMode => app.move_format_down(),
+                AppMode::QueueMode if key.modifiers.contains(KeyModifiers::SHIFT) => app.queue.shift_down(),
+                AppMode::QueueMode => app.queue.move_down(),
+                AppMode::OptionsMode => app.move_option_down(),
+                AppMode::CompareMode => {}
             }
         }
         KeyCode::Enter => {
-            app.command_buffer.clear();
             match app.mode {
                 AppMode::SelectMode => {
                     // Handle ".." entry for going up
@@ -282,16 +484,79 @@ pub fn handle_input(app: &mut AppState, key: KeyEvent) -> bool {
                 AppMode::ConvertMode => {
                     app.confirm_conversion();
                 }
+                AppMode::OptionsMode => {
+                    app.activate_option();
+                }
+                AppMode::QueueMode | AppMode::CompareMode => {}
             }
         }
         KeyCode::Esc => {
-            app.command_buffer.clear();
+            app.visual_anchor = None;
+            app.comparison = None;
             app.mode = AppMode::SelectMode;
         }
-        _ => {
-            app.command_buffer.clear();
-        }
+        _ => {}
     }
     
     false // Don't quit
-}
\ No newline at end of file
+}
+
+/// Run the single-key action `ch` stands for in the current mode.
+/// Returns false when it has none there.
+fn handle_mode_key(app: &mut AppState, ch: char) -> bool {
+    match (&app.mode, ch) {
+        // Toggle the mark on the highlighted file
+        (AppMode::SelectMode, ' ') => app.toggle_mark(),
+        // Invert marks in the current directory
+        (AppMode::SelectMode, '*') => app.invert_marks(),
+        // Mark every image in the current directory
+        (AppMode::SelectMode, 'a') => app.mark_all(),
+        // Start or finish a visual range selection
+        (AppMode::SelectMode, 'v') => app.toggle_visual(),
+        // Check the highlighted format as an extra target
+        (AppMode::ConvertMode, ' ') => app.toggle_format_check(),
+        // Queue a responsive srcset instead of single conversions
+        (AppMode::ConvertMode, 'w') => app.queue_responsive(),
+        // Remove the selected job
+        (AppMode::QueueMode, 'd') => {
+            if app.queue.remove_selected().is_none() {
+                app.status_message = Some("Cannot remove a running job".to_string());
+            }
+        }
+        // Compare the selected job's output with its source
+        (AppMode::QueueMode, 'm') => app.compare_selected_job(),
+        // Retry the selected job
+        (AppMode::QueueMode, 't') => {
+            if app.queue.retry_selected() && !app.queue.running {
+                app.status_message = Some("Job reset to pending (press r to start)".to_string());
+            }
+        }
+        // Toggle the difference heat-map
+        (AppMode::CompareMode, 'h') => {
+            if let Some(comparison) = app.comparison.as_mut() {
+                comparison.show_heatmap = !comparison.show_heatmap;
+            }
+        }
+        _ => return false,
+    }
+    true
+}
+
+/// Keys typed while an option is being edited go into the edit buffer
+fn handle_edit_input(app: &mut AppState, key: KeyEvent) {
+    match key.code {
+        KeyCode::Char(c) => {
+            if let Some(text) = app.editing.as_mut() {
+                text.push(c);
+            }
+        }
+        KeyCode::Backspace => {
+            if let Some(text) = app.editing.as_mut() {
+                text.pop();
+            }
+        }
+        KeyCode::Enter => app.commit_option(),
+        KeyCode::Esc => app.editing = None,
+        _ => {}
+    }
+}
@@ -1,3 +1,5 @@
 pub mod ui;
 pub mod events;
-
+pub mod queue;
+pub mod preview;
+pub mod settings;
@@ -0,0 +1,202 @@
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use ratatui_image::picker::{Picker, ProtocolType};
+use ratatui_image::protocol::StatefulProtocol;
+use crate::converter::compare::{self, Comparison};
+use crate::converter::formats::ImageFormat;
+use crate::converter::inspect::{inspect, ImageInfo};
+use crate::converter::main_converter;
+use crate::converter::options::ConversionOptions;
+
+/// Font size assumed when the terminal cannot be queried
+const FALLBACK_FONT_SIZE: (u16, u16) = (8, 16);
+
+/// Whether the output preview for `options` can differ from the real
+/// conversion, because it leaves out a quality search or the PNG optimizer
+pub fn is_approximate(options: &ConversionOptions) -> bool {
+    options.max_bytes.is_some() || options.min_ssim.is_some() || options.png_optimization.is_some()
+}
+
+/// `options` without the steps that encode the image over and over
+fn preview_options(options: &ConversionOptions) -> ConversionOptions {
+    ConversionOptions {
+        max_bytes: None,
+        allow_downscale: false,
+        min_ssim: None,
+        png_optimization: None,
+        ..options.clone()
+    }
+}
+
+struct CachedPreview {
+    path: PathBuf,
+    format: Option<ImageFormat>,
+    options: ConversionOptions,
+    image: Result<StatefulProtocol, String>,
+}
+
+/// Thumbnails of the highlighted file and of its converted output.
+/// Decoded images are cached until a different file or format is previewed.
+pub struct PreviewState {
+    picker: Picker,
+    source: Option<CachedPreview>,
+    output: Option<CachedPreview>,
+    info: Option<(PathBuf, Result<ImageInfo, String>)>,
+}
+
+impl PreviewState {
+    /// Query the terminal for a graphics protocol (kitty, iTerm2, sixel),
+    /// falling back to Unicode half blocks.
+    /// Must run after entering the alternate screen and before input is read.
+    pub fn from_terminal() -> Self {
+        let picker = Picker::from_query_stdio()
+            .unwrap_or_else(|_| Picker::from_fontsize(FALLBACK_FONT_SIZE));
+        Self::new(picker)
+    }
+
+    pub fn new(picker: Picker) -> Self {
+        Self {
+            picker,
+            source: None,
+            output: None,
+            info: None,
+        }
+    }
+
+    pub fn protocol_type(&self) -> ProtocolType {
+        self.picker.protocol_type()
+    }
+
+    /// Preview of the file as it is on disk
+    pub fn source(&mut self, path: &Path) -> &mut Result<StatefulProtocol, String> {
+        let picker = self.picker;
+        let cached = Self::cached(&mut self.source, path, None, &ConversionOptions::default(), || {
+            let image = image::open(path).map_err(|e| e.to_string())?;
+            Ok(picker.new_resize_protocol(image))
+        });
+        &mut cached.image
+    }
+
+    /// Preview of the file after conversion to `format`, encoded in memory only.
+    /// Runs on the UI thread, so it skips the quality searches and the PNG
+    /// optimizer; see `is_approximate`.
+    pub fn output(
+        &mut self,
+        path: &Path,
+        format: ImageFormat,
+        options: &ConversionOptions,
+    ) -> &mut Result<StatefulProtocol, String> {
+        let picker = self.picker;
+        let cached = Self::cached(&mut self.output, path, Some(format), options, || {
+            let source_format = ImageFormat::from_extension(path.to_str())
+                .ok_or_else(|| "Cannot determine input format".to_string())?;
+            let input_bytes = fs::read(path).map_err(|e| e.to_string())?;
+            let converted =
+                main_converter::convert_bytes(&input_bytes, Some(source_format), &format, &preview_options(options))
+                    .map_err(|e| e.to_string())?;
+            let image = image::load_from_memory(&converted).map_err(|e| e.to_string())?;
+            Ok(picker.new_resize_protocol(image))
+        });
+        &mut cached.image
+    }
+
+    /// Previews and quality metrics of a converted file next to its source
+    pub fn comparison(&self, source_path: &Path, output_path: &Path) -> ComparisonPreview {
+        let source = image::open(source_path).map_err(|e| e.to_string());
+        let output = image::open(output_path).map_err(|e| e.to_string());
+        let heatmap = match (&source, &output) {
+            (Ok(source), Ok(output)) => compare::difference_map(source, output)
+                .map(|map| self.picker.new_resize_protocol(map.into()))
+                .map_err(|e| e.to_string()),
+            (Err(e), _) | (_, Err(e)) => Err(e.clone()),
+        };
+
+        ComparisonPreview {
+            source_path: source_path.to_path_buf(),
+            output_path: output_path.to_path_buf(),
+            metrics: compare::compare(source_path, output_path).map_err(|e| e.to_string()),
+            source: source.map(|image| self.picker.new_resize_protocol(image)),
+            output: output.map(|image| self.picker.new_resize_protocol(image)),
+            heatmap,
+            show_heatmap: false,
+        }
+    }
+
+    /// Metadata shown in the inspector panel
+    pub fn info(&mut self, path: &Path) -> &Result<ImageInfo, String> {
+        if self.info.as_ref().is_none_or(|(cached, _)| cached != path) {
+            let info = inspect(path).map_err(|e| e.to_string());
+            self.info = Some((path.to_path_buf(), info));
+        }
+        &self.info.as_ref().expect("info was just cached").1
+    }
+
+    fn cached<'a>(
+        slot: &'a mut Option<CachedPreview>,
+        path: &Path,
+        format: Option<ImageFormat>,
+        options: &ConversionOptions,
+        load: impl FnOnce() -> Result<StatefulProtocol, String>,
+    ) -> &'a mut CachedPreview {
+        let stale = slot.as_ref().is_none_or(|cached| {
+            cached.path != path || cached.format != format || cached.options != *options
+        });
+        if stale {
+            *slot = Some(CachedPreview {
+                path: path.to_path_buf(),
+                format,
+                options: options.clone(),
+                image: load(),
+            });
+        }
+        slot.as_mut().expect("preview was just cached")
+    }
+
+    /// Drop cached previews, e.g. after files on disk have changed
+    pub fn invalidate(&mut self) {
+        self.source = None;
+        self.output = None;
+        self.info = None;
+    }
+}
+
+impl Default for PreviewState {
+    fn default() -> Self {
+        Self::new(Picker::from_fontsize(FALLBACK_FONT_SIZE))
+    }
+}
+
+impl fmt::Debug for PreviewState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PreviewState")
+            .field("picker", &self.picker)
+            .field("source", &self.source.as_ref().map(|cached| &cached.path))
+            .field("output", &self.output.as_ref().map(|cached| (&cached.path, cached.format)))
+            .field("info", &self.info.as_ref().map(|(path, _)| path))
+            .finish()
+    }
+}
+
+/// Source and converted output shown side by side in the compare view
+pub struct ComparisonPreview {
+    pub source_path: PathBuf,
+    pub output_path: PathBuf,
+    pub metrics: Result<Comparison, String>,
+    pub source: Result<StatefulProtocol, String>,
+    pub output: Result<StatefulProtocol, String>,
+    pub heatmap: Result<StatefulProtocol, String>,
+    /// Show the difference heat-map in place of the output
+    pub show_heatmap: bool,
+}
+
+impl fmt::Debug for ComparisonPreview {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ComparisonPreview")
+            .field("source_path", &self.source_path)
+            .field("output_path", &self.output_path)
+            .field("metrics", &self.metrics)
+            .field("show_heatmap", &self.show_heatmap)
+            .finish()
+    }
+}
@@ -0,0 +1,361 @@
+use std::fs;
+use std::path::PathBuf;
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::thread;
+use std::time::{Duration, Instant};
+use crate::converter::formats::ImageFormat;
+use crate::converter::main_converter::{self, ConversionReport};
+use crate::converter::options::ConversionOptions;
+use crate::converter::responsive::{self, ResponsiveSpec};
+use crate::converter::thumbnails::{self, ThumbnailSpec};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum JobStatus {
+    Pending,
+    Running,
+    Done,
+    /// The output already existed and the collision policy said to leave it
+    Skipped,
+    Failed(String),
+}
+
+/// What a job produces from its input
+#[derive(Debug, Clone, PartialEq)]
+pub enum JobKind {
+    /// One file in this format
+    Convert(ImageFormat),
+    /// One file in each of these formats, from a single decode of the input
+    ConvertMany(Vec<ImageFormat>),
+    /// A responsive set; `output_path` is its HTML snippet
+    Responsive(ResponsiveSpec),
+    /// Thumbnails of every image in the `input_path` directory; `output_path` is the contact sheet
+    ContactSheet(ThumbnailSpec),
+}
+
+#[derive(Debug, Clone)]
+pub struct ConversionJob {
+    pub input_path: PathBuf,
+    pub kind: JobKind,
+    pub options: ConversionOptions,
+    /// Where the job wrote its output, once it has run
+    pub output_path: Option<PathBuf>,
+    pub status: JobStatus,
+    pub elapsed: Option<Duration>,
+    pub bytes_before: Option<u64>,
+    pub bytes_after: Option<u64>,
+    /// Quality the `max_bytes` or `min_ssim` search settled on
+    pub chosen_quality: Option<u8>,
+    pub achieved_ssim: Option<f64>,
+    /// Part of the size reduction that came from the PNG optimizer
+    pub optimizer_savings: Option<u64>,
+    /// Number of images written, for jobs that write more than one
+    pub generated_files: Option<usize>,
+    /// Outcome of each target of a `ConvertMany` job, in target order
+    pub target_results: Vec<(ImageFormat, Result<ConversionReport, String>)>,
+}
+
+impl ConversionJob {
+    /// Job converting `input_path` to `target_format`
+    pub fn new(input_path: PathBuf, target_format: ImageFormat, options: ConversionOptions) -> Self {
+        let bytes_before = fs::metadata(&input_path).map(|m| m.len()).ok();
+        Self::pending(input_path, JobKind::Convert(target_format), options, bytes_before)
+    }
+
+    /// Job converting `input_path` to every format in `targets`; its `output_path`
+    /// is the first output written
+    pub fn convert_many(input_path: PathBuf, targets: Vec<ImageFormat>, options: ConversionOptions) -> Self {
+        let bytes_before = fs::metadata(&input_path).map(|m| m.len()).ok();
+        Self::pending(input_path, JobKind::ConvertMany(targets), options, bytes_before)
+    }
+
+    /// Job writing the responsive set `spec` of `input_path`
+    pub fn responsive(input_path: PathBuf, spec: ResponsiveSpec, options: ConversionOptions) -> Self {
+        let bytes_before = fs::metadata(&input_path).map(|m| m.len()).ok();
+        Self::pending(input_path, JobKind::Responsive(spec), options, bytes_before)
+    }
+
+    /// Job thumbnailing the images in `dir` onto a contact sheet
+    pub fn contact_sheet(dir: PathBuf, spec: ThumbnailSpec, options: ConversionOptions) -> Self {
+        Self::pending(dir, JobKind::ContactSheet(spec), options, None)
+    }
+
+    /// A job that hasn't run yet
+    fn pending(
+        input_path: PathBuf,
+        kind: JobKind,
+        options: ConversionOptions,
+        bytes_before: Option<u64>,
+    ) -> Self {
+        Self {
+            input_path,
+            kind,
+            options,
+            output_path: None,
+            status: JobStatus::Pending,
+            elapsed: None,
+            bytes_before,
+            bytes_after: None,
+            chosen_quality: None,
+            achieved_ssim: None,
+            optimizer_savings: None,
+            generated_files: None,
+            target_results: Vec::new(),
+        }
+    }
+
+    fn reset(&mut self) {
+        self.status = JobStatus::Pending;
+        self.output_path = None;
+        self.elapsed = None;
+        self.bytes_after = None;
+        self.chosen_quality = None;
+        self.achieved_ssim = None;
+        self.optimizer_savings = None;
+        self.generated_files = None;
+        self.target_results.clear();
+    }
+
+    fn run(&mut self) {
+        let started = Instant::now();
+        match &self.kind {
+            JobKind::Convert(target_format) => {
+                match main_converter::convert_with_options(&self.input_path, target_format, &self.options) {
+                    Ok(report) if report.skipped => {
+                        self.output_path = Some(report.output_path);
+                        self.status = JobStatus::Skipped;
+                    }
+                    Ok(report) => {
+                        self.output_path = Some(report.output_path);
+                        self.bytes_before = Some(report.input_bytes);
+                        self.bytes_after = Some(report.output_bytes);
+                        self.chosen_quality = report.chosen_quality;
+                        self.achieved_ssim = report.achieved_ssim;
+                        self.optimizer_savings = report.optimizer_savings;
+                        self.status = JobStatus::Done;
+                    }
+                    Err(e) => self.status = JobStatus::Failed(e.to_string()),
+                }
+            }
+            JobKind::ConvertMany(targets) => {
+                match main_converter::convert_many(&self.input_path, targets, &self.options) {
+                    Ok(results) => {
+                        self.target_results = targets
+                            .iter()
+                            .copied()
+                            .zip(results.into_iter().map(|result| result.map_err(|e| e.to_string())))
+                            .collect();
+                        let written: Vec<&ConversionReport> = self
+                            .target_results
+                            .iter()
+                            .filter_map(|(_, result)| result.as_ref().ok())
+                            .filter(|report| !report.skipped)
+                            .collect();
+                        if let Some(first) = written.first() {
+                            self.output_path = Some(first.output_path.clone());
+                            self.bytes_before = Some(first.input_bytes);
+                        }
+                        self.bytes_after = Some(written.iter().map(|report| report.output_bytes).sum());
+                        self.generated_files = Some(written.len());
+                        let failed = self.target_results.iter().filter(|(_, result)| result.is_err()).count();
+                        self.status = if failed > 0 {
+                            JobStatus::Failed(format!("{} of {} targets failed", failed, targets.len()))
+                        } else if written.is_empty() {
+                            JobStatus::Skipped
+                        } else {
+                            JobStatus::Done
+                        };
+                    }
+                    Err(e) => self.status = JobStatus::Failed(e.to_string()),
+                }
+            }
+            JobKind::Responsive(spec) => match responsive::generate(&self.input_path, spec, &self.options) {
+                Ok(set) => {
+                    self.output_path = Some(set.html_path);
+                    self.bytes_after = Some(set.variants.iter().map(|variant| variant.bytes).sum());
+                    self.generated_files = Some(set.variants.len());
+                    self.status = JobStatus::Done;
+                }
+                Err(e) => self.status = JobStatus::Failed(e.to_string()),
+            },
+            JobKind::ContactSheet(spec) => match thumbnails::generate(&self.input_path, spec, &self.options) {
+                Ok(report) => {
+                    self.output_path = Some(report.sheet_path);
+                    self.generated_files = Some(report.thumbnails.len());
+                    self.status = if report.failed.is_empty() {
+                        JobStatus::Done
+                    } else {
+                        JobStatus::Failed(format!("{} images could not be thumbnailed", report.failed.len()))
+                    };
+                }
+                Err(e) => self.status = JobStatus::Failed(e.to_string()),
+            },
+        }
+        self.elapsed = Some(started.elapsed());
+    }
+}
+
+/// What the worker thread reports about the job it runs
+#[derive(Debug)]
+enum JobUpdate {
+    Started(Instant),
+    Finished(Box<ConversionJob>),
+}
+
+/// The thread running the current job
+#[derive(Debug)]
+struct Worker {
+    updates: Receiver<JobUpdate>,
+    started: Option<Instant>,
+}
+
+/// Jobs waiting for, undergoing or finished with conversion.
+/// One job at a time runs on a worker thread; `step` collects its updates,
+/// so the UI keeps redrawing while it converts.
+#[derive(Debug, Default)]
+pub struct ConversionQueue {
+    pub jobs: Vec<ConversionJob>,
+    pub selected_index: usize,
+    pub running: bool,
+    worker: Option<Worker>,
+}
+
+impl ConversionQueue {
+    pub fn push(&mut self, input_path: PathBuf, target_format: ImageFormat, options: ConversionOptions) {
+        self.jobs.push(ConversionJob::new(input_path, target_format, options));
+    }
+
+    pub fn push_many(&mut self, input_path: PathBuf, targets: Vec<ImageFormat>, options: ConversionOptions) {
+        self.jobs.push(ConversionJob::convert_many(input_path, targets, options));
+    }
+
+    pub fn push_responsive(&mut self, input_path: PathBuf, spec: ResponsiveSpec, options: ConversionOptions) {
+        self.jobs.push(ConversionJob::responsive(input_path, spec, options));
+    }
+
+    pub fn push_contact_sheet(&mut self, dir: PathBuf, spec: ThumbnailSpec, options: ConversionOptions) {
+        self.jobs.push(ConversionJob::contact_sheet(dir, spec, options));
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.jobs.is_empty()
+    }
+
+    pub fn count(&self, status: &JobStatus) -> usize {
+        self.jobs
+            .iter()
+            .filter(|job| match (status, &job.status) {
+                (JobStatus::Failed(_), JobStatus::Failed(_)) => true,
+                (expected, actual) => expected == actual,
+            })
+            .count()
+    }
+
+    pub fn move_up(&mut self) {
+        self.selected_index = self.selected_index.saturating_sub(1);
+    }
+
+    pub fn move_down(&mut self) {
+        if self.selected_index + 1 < self.jobs.len() {
+            self.selected_index += 1;
+        }
+    }
+
+    /// Move the selected job one place earlier in the queue
+    pub fn shift_up(&mut self) {
+        if self.selected_index > 0 && self.selected_index < self.jobs.len() {
+            self.jobs.swap(self.selected_index, self.selected_index - 1);
+            self.selected_index -= 1;
+        }
+    }
+
+    /// Move the selected job one place later in the queue
+    pub fn shift_down(&mut self) {
+        if self.selected_index + 1 < self.jobs.len() {
+            self.jobs.swap(self.selected_index, self.selected_index + 1);
+            self.selected_index += 1;
+        }
+    }
+
+    pub fn remove_selected(&mut self) -> Option<ConversionJob> {
+        if self.selected_index >= self.jobs.len()
+            || self.jobs[self.selected_index].status == JobStatus::Running
+        {
+            return None;
+        }
+        let job = self.jobs.remove(self.selected_index);
+        if self.selected_index >= self.jobs.len() {
+            self.selected_index = self.jobs.len().saturating_sub(1);
+        }
+        Some(job)
+    }
+
+    /// Put a finished or failed job back into the pending state
+    pub fn retry_selected(&mut self) -> bool {
+        match self.jobs.get_mut(self.selected_index) {
+            Some(job) if job.status != JobStatus::Pending && job.status != JobStatus::Running => {
+                job.reset();
+                true
+            }
+            _ => false,
+        }
+    }
+
+    pub fn start(&mut self) -> bool {
+        self.running = self.count(&JobStatus::Pending) > 0;
+        self.running
+    }
+
+    /// Advance the queue by one step: take in what the worker reported, or hand it
+    /// the next pending job once it is free. Returns true once the last job has finished.
+    pub fn step(&mut self) -> bool {
+        if !self.running {
+            return false;
+        }
+        if let Some(worker) = &mut self.worker {
+            let Some(slot) = self.jobs.iter_mut().find(|job| job.status == JobStatus::Running) else {
+                self.worker = None;
+                return false;
+            };
+            let finished = loop {
+                match worker.updates.try_recv() {
+                    Ok(JobUpdate::Started(at)) => worker.started = Some(at),
+                    Ok(JobUpdate::Finished(job)) => {
+                        *slot = *job;
+                        break true;
+                    }
+                    Err(TryRecvError::Empty) => {
+                        slot.elapsed = worker.started.map(|at| at.elapsed());
+                        break false;
+                    }
+                    Err(TryRecvError::Disconnected) => {
+                        slot.status = JobStatus::Failed("The conversion stopped unexpectedly".to_string());
+                        break true;
+                    }
+                }
+            };
+            if finished {
+                self.worker = None;
+            }
+            return false;
+        }
+        match self.jobs.iter_mut().find(|job| job.status == JobStatus::Pending) {
+            Some(job) => {
+                job.status = JobStatus::Running;
+                let mut job = job.clone();
+                let (tx, rx) = mpsc::channel();
+                thread::spawn(move || {
+                    // The queue may be gone by the time the job finishes; nothing is left to tell then
+                    let _ = tx.send(JobUpdate::Started(Instant::now()));
+                    job.run();
+                    let _ = tx.send(JobUpdate::Finished(Box::new(job)));
+                });
+                self.worker = Some(Worker { updates: rx, started: None });
+                false
+            }
+            None => {
+                self.running = false;
+                true
+            }
+        }
+    }
+}
@@ -0,0 +1,495 @@
+use std::path::{Path, PathBuf};
+use crate::converter::filters;
+use crate::converter::formats::ImageFormat;
+use crate::converter::naming::{self, TemplateValues};
+use crate::converter::watermark::Logo;
+use crate::converter::options::{
+    Anchor, BackupPolicy, ChromaSubsampling, ConversionOptions, Watermark, WatermarkContent, WebpMode, WebpOptions,
+};
+
+/// A conversion option that can be changed from the options pane
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OptionField {
+    OutputDir,
+    NameTemplate,
+    Collision,
+    Quality,
+    MaxBytes,
+    AllowDownscale,
+    MinSsim,
+    MaxDimensions,
+    Filters,
+    Watermark,
+    WatermarkPosition,
+    WatermarkOpacity,
+    WatermarkScale,
+    WatermarkMargin,
+    JpegProgressive,
+    JpegOptimizeCoding,
+    JpegSubsampling,
+    PngOptimization,
+    WebpMode,
+    WebpMethod,
+    WebpAlphaQuality,
+    Streaming,
+    InPlace,
+    Backup,
+    DeleteOriginal,
+}
+
+impl OptionField {
+    pub const ALL: [OptionField; 25] = [
+        OptionField::OutputDir,
+        OptionField::NameTemplate,
+        OptionField::Collision,
+        OptionField::Quality,
+        OptionField::MaxBytes,
+        OptionField::AllowDownscale,
+        OptionField::MinSsim,
+        OptionField::MaxDimensions,
+        OptionField::Filters,
+        OptionField::Watermark,
+        OptionField::WatermarkPosition,
+        OptionField::WatermarkOpacity,
+        OptionField::WatermarkScale,
+        OptionField::WatermarkMargin,
+        OptionField::JpegProgressive,
+        OptionField::JpegOptimizeCoding,
+        OptionField::JpegSubsampling,
+        OptionField::PngOptimization,
+        OptionField::WebpMode,
+        OptionField::WebpMethod,
+        OptionField::WebpAlphaQuality,
+        OptionField::Streaming,
+        OptionField::InPlace,
+        OptionField::Backup,
+        OptionField::DeleteOriginal,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            OptionField::OutputDir => "Output directory",
+            OptionField::NameTemplate => "Name template",
+            OptionField::Collision => "On collision",
+            OptionField::Quality => "Quality",
+            OptionField::MaxBytes => "Max file size",
+            OptionField::AllowDownscale => "Shrink to fit",
+            OptionField::MinSsim => "Min SSIM",
+            OptionField::MaxDimensions => "Fit within",
+            OptionField::Filters => "Filters",
+            OptionField::Watermark => "Watermark",
+            OptionField::WatermarkPosition => "Watermark position",
+            OptionField::WatermarkOpacity => "Watermark opacity",
+            OptionField::WatermarkScale => "Watermark width",
+            OptionField::WatermarkMargin => "Watermark margin",
+            OptionField::JpegProgressive => "JPEG progressive",
+            OptionField::JpegOptimizeCoding => "JPEG Huffman tables",
+            OptionField::JpegSubsampling => "JPEG chroma",
+            OptionField::PngOptimization => "PNG optimization",
+            OptionField::WebpMode => "WebP compression",
+            OptionField::WebpMethod => "WebP effort",
+            OptionField::WebpAlphaQuality => "WebP alpha quality",
+            OptionField::Streaming => "Streaming",
+            OptionField::InPlace => "Same format",
+            OptionField::Backup => "Backup",
+            OptionField::DeleteOriginal => "Delete original",
+        }
+    }
+
+    /// Fields edited as text; the others cycle through their values on Enter
+    pub fn is_text(&self) -> bool {
+        !matches!(
+            self,
+            OptionField::Collision
+                | OptionField::AllowDownscale
+                | OptionField::JpegProgressive
+                | OptionField::JpegOptimizeCoding
+                | OptionField::JpegSubsampling
+                | OptionField::WebpMode
+                | OptionField::WatermarkPosition
+                | OptionField::Streaming
+                | OptionField::InPlace
+                | OptionField::DeleteOriginal
+        )
+    }
+
+    /// Current value as shown in the options pane
+    pub fn display(&self, options: &ConversionOptions) -> String {
+        match self {
+            OptionField::OutputDir => options
+                .output_dir
+                .as_ref()
+                .map(|dir| dir.display().to_string())
+                .unwrap_or_else(|| "<next to input>".to_string()),
+            OptionField::NameTemplate => options
+                .name_template
+                .clone()
+                .unwrap_or_else(|| naming::DEFAULT_TEMPLATE.to_string()),
+            OptionField::Collision => format!("{:?}", options.collision),
+            OptionField::Quality => options
+                .quality
+                .map(|q| q.to_string())
+                .unwrap_or_else(|| "<encoder default>".to_string()),
+            OptionField::MaxBytes => options
+                .max_bytes
+                .map(format_size)
+                .unwrap_or_else(|| "<no limit>".to_string()),
+            OptionField::AllowDownscale => if options.allow_downscale {
+                "Shrink when lowest quality is too big".to_string()
+            } else {
+                "Never".to_string()
+            },
+            OptionField::MinSsim => options
+                .min_ssim
+                .map(|ssim| format!("{} (searches quality)", ssim))
+                .unwrap_or_else(|| "<off>".to_string()),
+            OptionField::MaxDimensions => options
+                .max_dimensions
+                .map(|(width, height)| format!("{}x{}", width, height))
+                .unwrap_or_else(|| "<original size>".to_string()),
+            OptionField::Filters => if options.filters.is_empty() {
+                "<none>".to_string()
+            } else {
+                filters::describe(&options.filters)
+            },
+            OptionField::Watermark => match options.watermark.as_ref().map(|mark| &mark.content) {
+                None => "<none>".to_string(),
+                Some(WatermarkContent::Image(logo)) => logo.path().display().to_string(),
+                Some(WatermarkContent::Text(text)) => format!("\"{}\"", text),
+            },
+            OptionField::WatermarkPosition => match &options.watermark {
+                None => "<no watermark>".to_string(),
+                Some(mark) if mark.tile => "Tiled".to_string(),
+                Some(mark) => format!("{:?}", mark.anchor),
+            },
+            OptionField::WatermarkOpacity => options
+                .watermark
+                .as_ref()
+                .map(|mark| format!("{}%", (mark.opacity * 100.0).round()))
+                .unwrap_or_else(|| "<no watermark>".to_string()),
+            OptionField::WatermarkScale => options
+                .watermark
+                .as_ref()
+                .map(|mark| format!("{}% of image width", (mark.scale * 100.0).round()))
+                .unwrap_or_else(|| "<no watermark>".to_string()),
+            OptionField::WatermarkMargin => options
+                .watermark
+                .as_ref()
+                .map(|mark| format!("{} px", mark.margin))
+                .unwrap_or_else(|| "<no watermark>".to_string()),
+            OptionField::JpegProgressive => if options.jpeg.progressive {
+                "Progressive".to_string()
+            } else {
+                "Baseline".to_string()
+            },
+            OptionField::JpegOptimizeCoding => if options.jpeg.optimize_coding {
+                "Optimized for each image".to_string()
+            } else {
+                "Standard".to_string()
+            },
+            OptionField::JpegSubsampling => match options.jpeg.subsampling {
+                ChromaSubsampling::Auto => "<encoder default>".to_string(),
+                ChromaSubsampling::Yuv444 => "4:4:4 (graphics, text)".to_string(),
+                ChromaSubsampling::Yuv422 => "4:2:2".to_string(),
+                ChromaSubsampling::Yuv420 => "4:2:0 (photos)".to_string(),
+            },
+            OptionField::PngOptimization => options
+                .png_optimization
+                .map(|level| format!("Level {}", level))
+                .unwrap_or_else(|| "<off>".to_string()),
+            OptionField::WebpMode => match options.webp.mode {
+                WebpMode::Lossless => "Lossless".to_string(),
+                WebpMode::Lossy => "Lossy (at Quality)".to_string(),
+                WebpMode::NearLossless(level) => format!("Near-lossless {}", level),
+            },
+            OptionField::WebpMethod => options.webp.method.to_string(),
+            OptionField::WebpAlphaQuality => options.webp.alpha_quality.to_string(),
+            OptionField::Streaming => if options.streaming {
+                "Row by row (PNG and BMP only)".to_string()
+            } else {
+                "Decode whole image".to_string()
+            },
+            OptionField::InPlace => if options.in_place {
+                "Replace input in place".to_string()
+            } else {
+                "Write a new file".to_string()
+            },
+            OptionField::Backup => match &options.backup {
+                BackupPolicy::None => "<none>".to_string(),
+                BackupPolicy::Suffix(suffix) => format!("Copy to *{}", suffix),
+                BackupPolicy::TrashDir(dir) => format!("Copy into {}", dir.display()),
+            },
+            OptionField::DeleteOriginal => if options.delete_original {
+                "After cross-format conversion".to_string()
+            } else {
+                "Never".to_string()
+            },
+        }
+    }
+
+    /// Current value as the starting text of the editor
+    pub fn edit_text(&self, options: &ConversionOptions) -> String {
+        match self {
+            OptionField::OutputDir => options
+                .output_dir
+                .as_ref()
+                .map(|dir| dir.display().to_string())
+                .unwrap_or_default(),
+            OptionField::NameTemplate => options.name_template.clone().unwrap_or_default(),
+            OptionField::Quality => options.quality.map(|q| q.to_string()).unwrap_or_default(),
+            OptionField::MaxBytes => options.max_bytes.map(format_size).unwrap_or_default(),
+            OptionField::MinSsim => options.min_ssim.map(|ssim| ssim.to_string()).unwrap_or_default(),
+            OptionField::MaxDimensions => options
+                .max_dimensions
+                .map(|(width, height)| format!("{}x{}", width, height))
+                .unwrap_or_default(),
+            OptionField::Filters => filters::describe(&options.filters),
+            OptionField::Watermark => match options.watermark.as_ref().map(|mark| &mark.content) {
+                None => String::new(),
+                Some(WatermarkContent::Image(logo)) => logo.path().display().to_string(),
+                Some(WatermarkContent::Text(text)) => text.clone(),
+            },
+            OptionField::WatermarkOpacity => options
+                .watermark
+                .as_ref()
+                .map(|mark| (mark.opacity * 100.0).round().to_string())
+                .unwrap_or_default(),
+            OptionField::WatermarkScale => options
+                .watermark
+                .as_ref()
+                .map(|mark| (mark.scale * 100.0).round().to_string())
+                .unwrap_or_default(),
+            OptionField::WatermarkMargin => options
+                .watermark
+                .as_ref()
+                .map(|mark| mark.margin.to_string())
+                .unwrap_or_default(),
+            OptionField::PngOptimization => options
+                .png_optimization
+                .map(|level| level.to_string())
+                .unwrap_or_default(),
+            OptionField::WebpMethod => options.webp.method.to_string(),
+            OptionField::WebpAlphaQuality => options.webp.alpha_quality.to_string(),
+            OptionField::Backup => match &options.backup {
+                BackupPolicy::None => String::new(),
+                BackupPolicy::Suffix(suffix) => suffix.clone(),
+                BackupPolicy::TrashDir(dir) => dir.display().to_string(),
+            },
+            OptionField::Collision
+            | OptionField::AllowDownscale
+            | OptionField::JpegProgressive
+            | OptionField::JpegOptimizeCoding
+            | OptionField::JpegSubsampling
+            | OptionField::WebpMode
+            | OptionField::WatermarkPosition
+            | OptionField::Streaming
+            | OptionField::InPlace
+            | OptionField::DeleteOriginal => String::new(),
+        }
+    }
+
+    /// Parse edited text into the options. Empty text restores the default.
+    pub fn apply_text(&self, options: &mut ConversionOptions, text: &str) -> Result<(), String> {
+        let text = text.trim();
+        match self {
+            OptionField::OutputDir => {
+                options.output_dir = (!text.is_empty()).then(|| PathBuf::from(text));
+            }
+            OptionField::NameTemplate => {
+                if !text.is_empty() {
+                    // Render once with sample values to catch unknown tokens early
+                    naming::render_template(text, &TemplateValues {
+                        stem: "sample",
+                        format: ImageFormat::PNG,
+                        width: 1,
+                        height: 1,
+                        quality: Some(75),
+                    })
+                    .map_err(|e| e.to_string())?;
+                }
+                options.name_template = (!text.is_empty()).then(|| text.to_string());
+            }
+            OptionField::Backup => {
+                // ".bak" style text is a suffix, anything else a directory
+                options.backup = match text {
+                    "" | "none" => BackupPolicy::None,
+                    _ if text.starts_with('.') && !text.contains(['/', '\\']) => {
+                        BackupPolicy::Suffix(text.to_string())
+                    }
+                    _ => BackupPolicy::TrashDir(PathBuf::from(text)),
+                };
+            }
+            OptionField::WebpMethod => {
+                options.webp.method = match text {
+                    "" => WebpOptions::default().method,
+                    _ => match text.parse::<u8>() {
+                        Ok(method @ 0..=6) => method,
+                        _ => return Err("WebP effort must be a number from 0 to 6".to_string()),
+                    },
+                };
+            }
+            OptionField::WebpAlphaQuality => {
+                options.webp.alpha_quality = match text {
+                    "" => WebpOptions::default().alpha_quality,
+                    _ => match text.parse::<u8>() {
+                        Ok(quality @ 0..=100) => quality,
+                        _ => return Err("Alpha quality must be a number from 0 to 100".to_string()),
+                    },
+                };
+            }
+            OptionField::MaxBytes => {
+                options.max_bytes = match text {
+                    "" => None,
+                    _ => Some(parse_size(text).ok_or("Max file size must look like 250000, 500K or 2M")?),
+                };
+            }
+            OptionField::MinSsim => {
+                options.min_ssim = match text {
+                    "" => None,
+                    _ => match text.parse::<f64>() {
+                        Ok(ssim) if ssim > 0.0 && ssim <= 1.0 => Some(ssim),
+                        _ => return Err("Min SSIM must be a number above 0 and at most 1".to_string()),
+                    },
+                };
+            }
+            OptionField::MaxDimensions => {
+                options.max_dimensions = match text {
+                    "" => None,
+                    _ => Some(parse_dimensions(text).ok_or("Fit within must look like 1920x1080")?),
+                };
+            }
+            OptionField::Filters => {
+                options.filters = filters::parse(text)?;
+            }
+            OptionField::Watermark => {
+                // An existing file is a logo, anything else is the text to draw
+                let content = match text {
+                    "" => None,
+                    _ if Path::new(text).is_file() => {
+                        let logo = Logo::load(Path::new(text), &options.limits).map_err(|e| e.to_string())?;
+                        Some(WatermarkContent::Image(logo))
+                    }
+                    _ => Some(WatermarkContent::Text(text.to_string())),
+                };
+                options.watermark = match (content, options.watermark.take()) {
+                    (None, _) => None,
+                    (Some(content), Some(mark)) => Some(Watermark { content, ..mark }),
+                    (Some(content), None) => Some(Watermark::new(content)),
+                };
+            }
+            OptionField::WatermarkOpacity => {
+                let mark = options.watermark.as_mut().ok_or("Set a watermark first")?;
+                mark.opacity = match text {
+                    "" => 0.5,
+                    _ => match text.trim_end_matches('%').trim().parse::<u8>() {
+                        Ok(percent @ 0..=100) => percent as f32 / 100.0,
+                        _ => return Err("Watermark opacity must be a percentage from 0 to 100".to_string()),
+                    },
+                };
+            }
+            OptionField::WatermarkScale => {
+                let mark = options.watermark.as_mut().ok_or("Set a watermark first")?;
+                mark.scale = match text {
+                    "" => 0.25,
+                    _ => match text.trim_end_matches('%').trim().parse::<u8>() {
+                        Ok(percent @ 1..=100) => percent as f32 / 100.0,
+                        _ => return Err("Watermark width must be a percentage from 1 to 100".to_string()),
+                    },
+                };
+            }
+            OptionField::WatermarkMargin => {
+                let mark = options.watermark.as_mut().ok_or("Set a watermark first")?;
+                mark.margin = match text {
+                    "" => 16,
+                    _ => text.parse::<u32>().map_err(|_| "Watermark margin must be a number of pixels")?,
+                };
+            }
+            OptionField::Collision
+            | OptionField::AllowDownscale
+            | OptionField::JpegProgressive
+            | OptionField::JpegOptimizeCoding
+            | OptionField::JpegSubsampling
+            | OptionField::WebpMode
+            | OptionField::WatermarkPosition
+            | OptionField::Streaming
+            | OptionField::InPlace
+            | OptionField::DeleteOriginal => {}
+            OptionField::Quality => {
+                options.quality = match text {
+                    "" => None,
+                    _ => match text.parse::<u8>() {
+                        Ok(q @ 1..=100) => Some(q),
+                        _ => return Err("Quality must be a number from 1 to 100".to_string()),
+                    },
+                };
+            }
+            OptionField::PngOptimization => {
+                options.png_optimization = match text {
+                    "" => None,
+                    _ => match text.parse::<u8>() {
+                        Ok(level @ 0..=6) => Some(level),
+                        _ => return Err("PNG optimization must be a level from 0 to 6".to_string()),
+                    },
+                };
+            }
+        }
+        Ok(())
+    }
+
+    /// Advance a non-text field to its next value
+    pub fn cycle(&self, options: &mut ConversionOptions) {
+        match self {
+            OptionField::Collision => options.collision = options.collision.next(),
+            OptionField::AllowDownscale => options.allow_downscale = !options.allow_downscale,
+            OptionField::JpegProgressive => options.jpeg.progressive = !options.jpeg.progressive,
+            OptionField::JpegOptimizeCoding => options.jpeg.optimize_coding = !options.jpeg.optimize_coding,
+            OptionField::JpegSubsampling => options.jpeg.subsampling = options.jpeg.subsampling.next(),
+            OptionField::WebpMode => options.webp.mode = options.webp.mode.next(),
+            // Every anchor in turn, then tiled across the image
+            OptionField::WatermarkPosition => {
+                if let Some(mark) = options.watermark.as_mut() {
+                    match (mark.tile, mark.anchor.next()) {
+                        (true, _) => (mark.tile, mark.anchor) = (false, Anchor::ALL[0]),
+                        (false, Anchor::TopLeft) => mark.tile = true,
+                        (false, next) => mark.anchor = next,
+                    }
+                }
+            }
+            OptionField::Streaming => options.streaming = !options.streaming,
+            OptionField::InPlace => options.in_place = !options.in_place,
+            OptionField::DeleteOriginal => options.delete_original = !options.delete_original,
+            _ => {}
+        }
+    }
+}
+
+/// Parse a byte count with an optional K or M suffix (powers of 1024)
+fn parse_size(text: &str) -> Option<u64> {
+    let upper = text.to_uppercase();
+    let upper = upper.trim_end_matches('B');
+    let (digits, multiplier) = match upper.char_indices().last()? {
+        (i, 'K') => (&upper[..i], 1024),
+        (i, 'M') => (&upper[..i], 1024 * 1024),
+        _ => (upper, 1),
+    };
+    let size = digits.trim().parse::<u64>().ok()?.checked_mul(multiplier)?;
+    (size > 0).then_some(size)
+}
+
+/// Parse "WIDTHxHEIGHT" with both sides above zero
+fn parse_dimensions(text: &str) -> Option<(u32, u32)> {
+    let lower = text.to_lowercase();
+    let (width, height) = lower.split_once('x')?;
+    let width = width.trim().parse::<u32>().ok()?;
+    let height = height.trim().parse::<u32>().ok()?;
+    (width > 0 && height > 0).then_some((width, height))
+}
+
+/// Byte count in the shortest form `parse_size` reads back
+fn format_size(bytes: u64) -> String {
+    match bytes {
+        _ if bytes.is_multiple_of(1024 * 1024) => format!("{}M", bytes / (1024 * 1024)),
+        _ if bytes.is_multiple_of(1024) => format!("{}K", bytes / 1024),
+        _ => bytes.to_string(),
+    }
+}
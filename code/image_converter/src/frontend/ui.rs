@@ -1,17 +1,23 @@
 use ratatui::{
-    backend::Backend,
     layout::{Alignment, Constraint, Direction, Layout, Rect},
     style::{Color, Modifier, Style},
-    text::{Line},
+    text::{Line, Span},
     widgets::{Block, Borders, List, ListItem, ListState, Paragraph},
     Frame,
 };
+use ratatui_image::{protocol::StatefulProtocol, StatefulImage};
+use crate::converter::formats::ImageFormat;
 use crate::frontend::events::{AppMode, AppState};
+use crate::frontend::preview::{self, ComparisonPreview};
+use crate::frontend::settings::OptionField;
+use crate::frontend::queue::{ConversionJob, JobKind, JobStatus};
 
 const BROWN: Color = Color::Rgb(101, 67, 33);
 const DARK_GREEN: Color = Color::Rgb(0, 100, 0);
+const QUEUE_PANE_HEIGHT: u16 = 8;
+const INFO_PANEL_MAX_HEIGHT: u16 = 14;
 
-pub fn draw<B: Backend>(f: &mut Frame, app: &AppState) {
+pub fn draw(f: &mut Frame, app: &mut AppState) {
     let size = f.area();
     
     if size.width < 80 {
@@ -26,21 +32,32 @@ pub fn draw<B: Backend>(f: &mut Frame, app: &AppState) {
 
     let main_chunks = Layout::default()
         .direction(Direction::Vertical)
-        .constraints([Constraint::Min(0), Constraint::Length(1)])
+        .constraints([Constraint::Min(0), Constraint::Length(QUEUE_PANE_HEIGHT), Constraint::Length(1)])
         .split(size);
 
-    let pane_chunks = Layout::default()
-        .direction(Direction::Horizontal)
-        .constraints([Constraint::Percentage(50), Constraint::Percentage(50)])
-        .split(main_chunks[0]);
+    if app.mode == AppMode::CompareMode
+        && let Some(comparison) = app.comparison.as_mut()
+    {
+        draw_compare_view(f, comparison, main_chunks[0]);
+    } else {
+        let pane_chunks = Layout::default()
+            .direction(Direction::Horizontal)
+            .constraints([Constraint::Percentage(50), Constraint::Percentage(50)])
+            .split(main_chunks[0]);
 
-    draw_directory_pane::<B>(f, app, pane_chunks[0]);
-    draw_conversion_pane::<B>(f, app, pane_chunks[1]);
-    draw_status_bar::<B>(f, app, main_chunks[1]);
+        draw_directory_pane(f, app, pane_chunks[0]);
+        if app.mode == AppMode::OptionsMode {
+            draw_options_pane(f, app, pane_chunks[1]);
+        } else {
+            draw_conversion_pane(f, app, pane_chunks[1]);
+        }
+    }
+    draw_queue_pane(f, app, main_chunks[1]);
+    draw_status_bar(f, app, main_chunks[2]);
 
 
 }
-fn draw_directory_pane<B: Backend>(f: &mut Frame, app: &AppState, area: Rect) {
+fn draw_directory_pane(f: &mut Frame, app: &AppState, area: Rect) {
     let mut items = Vec::new();
     let mut list_state = ListState::default();
     
@@ -51,15 +68,15 @@ fn draw_directory_pane<B: Backend>(f: &mut Frame, app: &AppState, area: Rect) {
         index_offset = 1;
     }
     
-    for entry in &app.entries {
+    for (i, entry) in app.entries.iter().enumerate() {
         let name = entry.file_name().to_string_lossy().to_string();
-        let display_name = if entry.metadata().map(|m| m.is_dir()).unwrap_or(false) {
-            format!("{}/", name)
+        if entry.metadata().map(|m| m.is_dir()).unwrap_or(false) {
+            items.push(ListItem::new(format!("{}/", name)));
+        } else if app.is_marked(i + index_offset, &entry.path()) {
+            items.push(ListItem::new(format!("✔ {}", name)).style(Style::default().fg(Color::LightGreen)));
         } else {
-            name
-        };
-        
-        items.push(ListItem::new(display_name));
+            items.push(ListItem::new(name));
+        }
     }
     
     if items.is_empty() || (items.len() == 1 && index_offset == 1) {
@@ -76,8 +93,14 @@ fn draw_directory_pane<B: Backend>(f: &mut Frame, app: &AppState, area: Rect) {
         Style::default().fg(Color::Gray)
     };
     
+    let title = match (app.visual_anchor, app.marked.len()) {
+        (Some(_), marked) => format!("Select File ({} marked, VISUAL)", marked),
+        (None, 0) => "Select File".to_string(),
+        (None, marked) => format!("Select File ({} marked)", marked),
+    };
+
     let block = Block::default()
-        .title("Select File")
+        .title(title)
         .borders(Borders::ALL)
         .border_style(border_style)
         .style(Style::default().bg(BROWN));
@@ -90,7 +113,7 @@ fn draw_directory_pane<B: Backend>(f: &mut Frame, app: &AppState, area: Rect) {
     f.render_stateful_widget(list, area, &mut list_state);
 }
 
-fn draw_conversion_pane<B: Backend>(f: &mut Frame, app: &AppState, area: Rect) {
+fn draw_conversion_pane(f: &mut Frame, app: &mut AppState, area: Rect) {
     let border_style = if app.mode == AppMode::ConvertMode {
         Style::default().fg(Color::White).add_modifier(Modifier::BOLD)
     } else {
@@ -106,33 +129,65 @@ fn draw_conversion_pane<B: Backend>(f: &mut Frame, app: &AppState, area: Rect) {
     let inner_area = block.inner(area);
     f.render_widget(block, area);
     
-    match (&app.selected_file, &app.mode) {
+    if !app.marked.is_empty() {
+        draw_marked_files(f, app, inner_area);
+        return;
+    }
+
+    match (app.selected_file.clone(), &app.mode) {
         (None, _) => {
-            let msg = Paragraph::new("No file selected")
-                .alignment(Alignment::Center)
-                .style(Style::default().fg(Color::White));
-            
-            let centered_area = centered_rect(50, 20, inner_area);
-            f.render_widget(msg, centered_area);
+            let Some(highlighted) = app.highlighted_image() else {
+                let msg = Paragraph::new("No file selected")
+                    .alignment(Alignment::Center)
+                    .style(Style::default().fg(Color::White));
+                
+                let centered_area = centered_rect(50, 20, inner_area);
+                f.render_widget(msg, centered_area);
+                return;
+            };
+
+            let chunks = Layout::default()
+                .direction(Direction::Vertical)
+                .constraints([Constraint::Length(1), Constraint::Min(0)])
+                .split(inner_area);
+
+            f.render_widget(
+                Paragraph::new("No file selected").style(Style::default().fg(Color::White)),
+                chunks[0],
+            );
+
+            draw_file_details(f, app, &highlighted, chunks[1]);
         }
-        (Some(file_path), AppMode::SelectMode) => {
+        (Some(file_path), AppMode::SelectMode | AppMode::QueueMode | AppMode::CompareMode | AppMode::OptionsMode) => {
+            let chunks = Layout::default()
+                .direction(Direction::Vertical)
+                .constraints([Constraint::Length(4), Constraint::Min(0)])
+                .split(inner_area);
+
             let text = vec![
                 Line::from("Selected file:"),
                 Line::from(file_path.display().to_string()),
                 Line::from(""),
-                Line::from("(Type \"conv\" to choose format)"),
+                Line::from("(Type \"conv\" to choose format, \"list\" for the queue, \"opt\" for options)"),
             ];
             
             let paragraph = Paragraph::new(text)
                 .style(Style::default().fg(Color::White))
                 .alignment(Alignment::Left);
             
-            f.render_widget(paragraph, inner_area);
+            f.render_widget(paragraph, chunks[0]);
+
+            let previewed = app.highlighted_image().unwrap_or(file_path);
+            draw_file_details(f, app, &previewed, chunks[1]);
         }
         (Some(file_path), AppMode::ConvertMode) => {
             let chunks = Layout::default()
                 .direction(Direction::Vertical)
-                .constraints([Constraint::Length(3), Constraint::Min(0)])
+                .constraints([
+                    Constraint::Length(3),
+                    Constraint::Length(ImageFormat::ALL.len() as u16 + 2),
+                    Constraint::Min(0),
+                ])
                 .split(inner_area);
             
             let text = vec![
@@ -145,25 +200,166 @@ fn draw_conversion_pane<B: Backend>(f: &mut Frame, app: &AppState, area: Rect) {
             
             f.render_widget(file_info, chunks[0]);
             
-            draw_format_selection::<B>(f, app, chunks[1]);
+            draw_format_selection(f, app, chunks[1]);
+
+            let preview_chunks = Layout::default()
+                .direction(Direction::Horizontal)
+                .constraints([Constraint::Percentage(50), Constraint::Percentage(50)])
+                .split(chunks[2]);
+
+            let target_format = ImageFormat::ALL[app.selected_format_index];
+            draw_preview(f, preview_title(&file_path), app.preview.source(&file_path), preview_chunks[0]);
+            draw_preview(
+                f,
+                if preview::is_approximate(&app.options) {
+                    format!("As {:?} (approximate)", target_format)
+                } else {
+                    format!("As {:?}", target_format)
+                },
+                app.preview.output(&file_path, target_format, &app.options),
+                preview_chunks[1],
+            );
         }
     }
 }
 
-fn draw_format_selection<B: Backend>(f: &mut Frame, app: &AppState, area: Rect) {
-    
-    let format_names = ["PNG", "JPEG", "WEBP", "GIF", "BMP"];
-    
-    let items: Vec<ListItem> = format_names
+/// Preview of the file with the metadata inspector underneath
+fn draw_file_details(f: &mut Frame, app: &mut AppState, path: &std::path::Path, area: Rect) {
+    let lines: Vec<Line> = match app.preview.info(path) {
+        Ok(info) => info
+            .fields()
+            .into_iter()
+            .map(|(label, value)| {
+                Line::from(vec![
+                    Span::styled(format!("{:<14}", label), Style::default().fg(Color::Yellow)),
+                    Span::raw(value),
+                ])
+            })
+            .collect(),
+        Err(e) => vec![Line::from(Span::styled(e.clone(), Style::default().fg(Color::Red)))],
+    };
+
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Min(0),
+            Constraint::Length((lines.len() as u16 + 2).min(INFO_PANEL_MAX_HEIGHT)),
+        ])
+        .split(area);
+
+    draw_preview(f, preview_title(path), app.preview.source(path), chunks[0]);
+
+    let info = Paragraph::new(lines)
+        .block(Block::default().title("Info").borders(Borders::ALL))
+        .style(Style::default().fg(Color::White));
+    f.render_widget(info, chunks[1]);
+}
+
+fn preview_title(path: &std::path::Path) -> String {
+    path.file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default()
+}
+
+fn draw_preview(
+    f: &mut Frame,
+    title: String,
+    preview: &mut Result<StatefulProtocol, String>,
+    area: Rect,
+) {
+    let block = Block::default()
+        .title(title)
+        .borders(Borders::ALL)
+        .style(Style::default().bg(BROWN));
+
+    let inner_area = block.inner(area);
+    f.render_widget(block, area);
+
+    match preview {
+        Ok(image) => f.render_stateful_widget(StatefulImage::default(), inner_area, image),
+        Err(e) => f.render_widget(
+            Paragraph::new(format!("Preview unavailable: {}", e))
+                .style(Style::default().fg(Color::Red)),
+            inner_area,
+        ),
+    }
+}
+
+fn draw_marked_files(f: &mut Frame, app: &AppState, area: Rect) {
+    let header = Line::from(format!("Marked files ({}):", app.marked.len()));
+
+    if app.mode == AppMode::ConvertMode {
+        let chunks = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([Constraint::Length(3), Constraint::Min(0)])
+            .split(area);
+
+        let text = vec![header, Line::from("Enter queues all of them")];
+        f.render_widget(Paragraph::new(text).style(Style::default().fg(Color::White)), chunks[0]);
+
+        draw_format_selection(f, app, chunks[1]);
+        return;
+    }
+
+    let mut text = vec![header];
+    text.extend(app.marked.iter().map(|path| Line::from(format!("  {}", path.display()))));
+    text.push(Line::from(""));
+    text.push(Line::from("(Type \"conv\" to convert all marked files)"));
+
+    let paragraph = Paragraph::new(text)
+        .style(Style::default().fg(Color::White))
+        .alignment(Alignment::Left);
+
+    f.render_widget(paragraph, area);
+}
+
+fn draw_options_pane(f: &mut Frame, app: &AppState, area: Rect) {
+    let block = Block::default()
+        .title("Options (Enter edits, Esc leaves)")
+        .borders(Borders::ALL)
+        .border_style(Style::default().fg(Color::White).add_modifier(Modifier::BOLD))
+        .style(Style::default().bg(BROWN));
+
+    let items: Vec<ListItem> = OptionField::ALL
         .iter()
-        .map(|&name| ListItem::new(format!("• {}", name)))
+        .enumerate()
+        .map(|(i, field)| {
+            let value = match &app.editing {
+                Some(text) if i == app.selected_option_index => format!("{}█", text),
+                _ => field.display(&app.options),
+            };
+            ListItem::new(Line::from(vec![
+                Span::styled(format!("{:<20}", field.label()), Style::default().fg(Color::Yellow)),
+                Span::raw(value),
+            ]))
+        })
+        .collect();
+
+    let mut list_state = ListState::default();
+    list_state.select(Some(app.selected_option_index));
+
+    let list = List::new(items)
+        .block(block)
+        .highlight_style(Style::default().bg(DARK_GREEN).fg(Color::White))
+        .highlight_symbol("▶ ");
+
+    f.render_stateful_widget(list, area, &mut list_state);
+}
+
+fn draw_format_selection(f: &mut Frame, app: &AppState, area: Rect) {
+    let items: Vec<ListItem> = ImageFormat::ALL
+        .iter()
+        .map(|format| {
+            let check = if app.checked_formats.contains(format) { "[x]" } else { "[ ]" };
+            ListItem::new(format!("{} {:?}", check, format))
+        })
         .collect();
     
     let mut list_state = ListState::default();
     list_state.select(Some(app.selected_format_index));
     
     let block = Block::default()
-        .title("Target format:")
+        .title("Targets (Space checks, Enter queues, w: srcset)")
         .borders(Borders::ALL)
         .style(Style::default().bg(BROWN));
     
@@ -175,12 +371,175 @@ fn draw_format_selection<B: Backend>(f: &mut Frame, app: &AppState, area: Rect)
     f.render_stateful_widget(list, area, &mut list_state);
 }
 
-fn draw_status_bar<B: Backend>(f: &mut Frame, app: &AppState, area: Rect) {
+fn draw_compare_view(f: &mut Frame, comparison: &mut ComparisonPreview, area: Rect) {
+    let block = Block::default()
+        .title("Compare (h toggles the difference map, Esc closes)")
+        .borders(Borders::ALL)
+        .border_style(Style::default().fg(Color::White).add_modifier(Modifier::BOLD))
+        .style(Style::default().bg(BROWN));
+
+    let inner_area = block.inner(area);
+    f.render_widget(block, area);
+
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Min(0), Constraint::Length(2)])
+        .split(inner_area);
+
+    let preview_chunks = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([Constraint::Percentage(50), Constraint::Percentage(50)])
+        .split(chunks[0]);
+
+    draw_preview(f, preview_title(&comparison.source_path), &mut comparison.source, preview_chunks[0]);
+    if comparison.show_heatmap {
+        draw_preview(f, "Difference".to_string(), &mut comparison.heatmap, preview_chunks[1]);
+    } else {
+        draw_preview(f, preview_title(&comparison.output_path), &mut comparison.output, preview_chunks[1]);
+    }
+
+    let metrics = match &comparison.metrics {
+        Ok(metrics) => vec![
+            Line::from(format!(
+                "PSNR {:.2} dB   SSIM {:.4}",
+                metrics.psnr, metrics.ssim
+            )),
+            Line::from(format!(
+                "{} → {} ({:+.1}% size)",
+                format_bytes(metrics.reference_bytes),
+                format_bytes(metrics.distorted_bytes),
+                -metrics.savings() * 100.0
+            )),
+        ],
+        Err(e) => vec![Line::from(Span::styled(e.clone(), Style::default().fg(Color::Red)))],
+    };
+    f.render_widget(Paragraph::new(metrics).style(Style::default().fg(Color::White)), chunks[1]);
+}
+
+fn draw_queue_pane(f: &mut Frame, app: &AppState, area: Rect) {
+    let border_style = if app.mode == AppMode::QueueMode {
+        Style::default().fg(Color::White).add_modifier(Modifier::BOLD)
+    } else {
+        Style::default().fg(Color::Gray)
+    };
+
+    let title = format!(
+        "Queue ({} pending, {} done, {} failed) - r to start",
+        app.queue.count(&JobStatus::Pending),
+        app.queue.count(&JobStatus::Done),
+        app.queue.count(&JobStatus::Failed(String::new()))
+    );
+
+    let block = Block::default()
+        .title(title)
+        .borders(Borders::ALL)
+        .border_style(border_style)
+        .style(Style::default().bg(BROWN));
+
+    let mut items: Vec<ListItem> = app.queue.jobs.iter().map(job_list_item).collect();
+    if items.is_empty() {
+        items.push(ListItem::new("<no jobs queued>").style(Style::default().fg(Color::DarkGray)));
+    }
+
+    let mut list_state = ListState::default();
+    if app.mode == AppMode::QueueMode && !app.queue.is_empty() {
+        list_state.select(Some(app.queue.selected_index));
+    }
+
+    let list = List::new(items)
+        .block(block)
+        .highlight_style(Style::default().bg(DARK_GREEN).fg(Color::White))
+        .highlight_symbol("▶ ");
+
+    f.render_stateful_widget(list, area, &mut list_state);
+}
+
+fn job_list_item(job: &ConversionJob) -> ListItem<'static> {
+    let (label, color) = match &job.status {
+        JobStatus::Pending => ("PENDING".to_string(), Color::Gray),
+        JobStatus::Running => ("RUNNING".to_string(), Color::Yellow),
+        JobStatus::Done => ("DONE".to_string(), Color::Green),
+        JobStatus::Skipped => ("SKIPPED".to_string(), Color::Cyan),
+        JobStatus::Failed(reason) => (format!("FAILED: {}", reason), Color::Red),
+    };
+
+    let mut text = format!("{} → {}", file_name(&job.input_path), job_output(job));
+    if let Some(before) = job.bytes_before {
+        text.push_str(&format!("  {}", format_bytes(before)));
+    }
+    if let Some(after) = job.bytes_after {
+        text.push_str(&format!(" → {}", format_bytes(after)));
+    }
+    if let Some(quality) = job.chosen_quality {
+        text.push_str(&format!(" at q{}", quality));
+    }
+    if let Some(ssim) = job.achieved_ssim {
+        text.push_str(&format!(" (SSIM {:.4})", ssim));
+    }
+    if let Some(files) = job.generated_files {
+        text.push_str(&format!(" in {} images", files));
+    }
+    if let Some(saved) = job.optimizer_savings {
+        text.push_str(&format!(" (optimizer -{})", format_bytes(saved)));
+    }
+    for (target, result) in &job.target_results {
+        match result {
+            Ok(report) if report.skipped => text.push_str(&format!("  {:?}: skipped", target)),
+            Ok(report) => text.push_str(&format!("  {:?}: {}", target, format_bytes(report.output_bytes))),
+            Err(e) => text.push_str(&format!("  {:?}: {}", target, e)),
+        }
+    }
+    if let Some(elapsed) = job.elapsed {
+        text.push_str(&format!("  {:.2}s", elapsed.as_secs_f64()));
+    }
+
+    ListItem::new(Line::from(vec![
+        Span::styled(format!("[{}] ", label), Style::default().fg(color)),
+        Span::raw(text),
+    ]))
+}
+
+fn file_name(path: &std::path::Path) -> String {
+    path.file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default()
+}
+
+/// The file a job wrote, or what it will produce while it hasn't run yet
+fn job_output(job: &ConversionJob) -> String {
+    if let Some(path) = &job.output_path {
+        return file_name(path);
+    }
+    match &job.kind {
+        JobKind::Convert(target) => format!("{:?}", target),
+        JobKind::ConvertMany(targets) => {
+            targets.iter().map(|target| format!("{:?}", target)).collect::<Vec<_>>().join(", ")
+        }
+        JobKind::Responsive(_) => "responsive set".to_string(),
+        JobKind::ContactSheet(_) => "contact sheet".to_string(),
+    }
+}
+
+fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["B", "KB", "MB", "GB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} {}", bytes, UNITS[unit])
+    } else {
+        format!("{:.1} {}", value, UNITS[unit])
+    }
+}
+
+fn draw_status_bar(f: &mut Frame, app: &AppState, area: Rect) {
     let status_text = if let Some(ref message) = app.status_message {
         message.clone()
-    } else if !app.to_convert.is_empty() {
-        let (path, format) = &app.to_convert[app.to_convert.len() - 1];
-        format!("Will convert {} → {:?}", path.display(), format).replace("\"", "")
+    } else if let Some(job) = app.queue.jobs.last() {
+        format!("Will convert {} → {}", job.input_path.display(), job_output(job))
     } else {
         String::new()
     };
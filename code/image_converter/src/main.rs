@@ -1,5 +1,4 @@
-mod frontend;
-mod converter;
+mod cli;
 
 use crossterm::{
     event::{self, DisableMouseCapture, EnableMouseCapture, Event, KeyCode},
@@ -19,12 +18,24 @@ use std::{
     time::{Duration, Instant},
 };
 
-use frontend::events::{AppEvent, AppState, handle_input};
-use frontend::ui::draw;
+use image_converter::frontend::events::{AppEvent, AppState, handle_input};
+use image_converter::frontend::preview::PreviewState;
+use image_converter::frontend::queue::JobStatus;
+use image_converter::frontend::ui::draw;
 
 const TICK_RATE: Duration = Duration::from_millis(250);
 
 fn main() -> Result<(), Box<dyn Error>> {
+    let args: Vec<String> = env::args().skip(1).collect();
+    if !args.is_empty() {
+        // Print the message itself rather than the Debug form `main` would show
+        if let Err(e) = cli::run(&args) {
+            eprintln!("error: {}", e);
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+
     enable_raw_mode()?;
     let mut stdout = io::stdout();
     execute!(stdout, EnterAlternateScreen, EnableMouseCapture)?;
@@ -36,6 +47,7 @@ fn main() -> Result<(), Box<dyn Error>> {
     });
     
     let mut app = AppState::new(cwd);
+    app.preview = PreviewState::from_terminal();
 
     let (tx, rx) = mpsc::channel();
     let tick_rate = TICK_RATE;
@@ -47,26 +59,24 @@ fn main() -> Result<(), Box<dyn Error>> {
                 .checked_sub(last_tick.elapsed())
                 .unwrap_or_else(|| Duration::from_secs(0));
 
-            if event::poll(timeout).expect("poll works") {
-                if let Event::Key(key) = event::read().expect("can read events") {
-                    tx.send(AppEvent::Input(key)).expect("can send events");
-                }
+            if event::poll(timeout).expect("poll works")
+                && let Event::Key(key) = event::read().expect("can read events")
+            {
+                tx.send(AppEvent::Input(key)).expect("can send events");
             }
 
-            if last_tick.elapsed() >= tick_rate {
-                if let Ok(_) = tx.send(AppEvent::Tick) {
-                    last_tick = Instant::now();
-                }
+            if last_tick.elapsed() >= tick_rate && tx.send(AppEvent::Tick).is_ok() {
+                last_tick = Instant::now();
             }
         }
     });
 
     loop {
-        terminal.draw(|f: &mut ratatui::Frame | draw::<CrosstermBackend<std::io::Stdout>>(f, &app))?;
+        terminal.draw(|f: &mut ratatui::Frame | draw(f, &mut app))?;
 
         match rx.recv()? {
             AppEvent::Input(key) => {
-                if key.code == KeyCode::Char('q') && app.command_buffer.is_empty() {
+                if key.code == KeyCode::Char('q') && app.editing.is_none() {
                     break;
                 }
                 
@@ -75,6 +85,7 @@ fn main() -> Result<(), Box<dyn Error>> {
                 }
             }
             AppEvent::Tick => {
+                app.on_tick();
             }
         }
     }
@@ -88,10 +99,21 @@ fn main() -> Result<(), Box<dyn Error>> {
     )?;
     terminal.show_cursor()?;
 
-    if !app.to_convert.is_empty() {
-        println!("Selected files for conversion:");
-        for (path, format) in &app.to_convert {
-            println!("  {} → {:?}", path.display(), format);
+    if !app.queue.is_empty() {
+        println!("Conversion queue:");
+        for job in &app.queue.jobs {
+            let status = match &job.status {
+                JobStatus::Pending => "pending".to_string(),
+                JobStatus::Running => "running".to_string(),
+                JobStatus::Done => "done".to_string(),
+                JobStatus::Skipped => "skipped".to_string(),
+                JobStatus::Failed(reason) => format!("failed: {}", reason),
+            };
+            let output = match &job.output_path {
+                Some(path) => path.display().to_string(),
+                None => "nothing written".to_string(),
+            };
+            println!("  {} → {} [{}]", job.input_path.display(), output, status);
         }
     }
 
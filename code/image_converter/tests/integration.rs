@@ -1,17 +1,950 @@
 #[cfg(test)]
 pub mod tests {
-    use std::path::Path;
-    use image_converter::converter::{formats::ImageFormat, main_converter::convert};
+    use std::fs;
+    use std::path::{Path, PathBuf};
+    use image_converter::converter::{self, formats::ImageFormat, main_converter::convert};
+    use image_converter::converter::errors::{ConverterError, LimitKind, Stage};
+    use image_converter::converter::responsive::{self, ResponsiveSpec};
+    use image_converter::converter::thumbnails::{self, ThumbnailSpec};
+    use image_converter::converter::atlas::{self, AtlasSpec};
+    use image_converter::converter::main_converter::convert_with_options;
+    use image_converter::converter::options::{
+        Anchor, BackupPolicy, ChromaSubsampling, CollisionPolicy, ConversionOptions, Filter, InputLimits,
+        JpegOptions, Watermark, WatermarkContent, WebpMode, WebpOptions,
+    };
+    use image_converter::converter::filters;
+    use image_converter::converter::watermark::Logo;
+    use image_converter::converter::presets::FilterPresets;
+    use image_converter::frontend::settings::OptionField;
+    use image::GenericImageView;
+    use image_converter::frontend::events::{handle_input, AppMode, AppState};
+    use image_converter::frontend::ui::draw;
+    use ratatui::{backend::TestBackend, Terminal};
+    use image_converter::frontend::queue::{ConversionQueue, JobKind, JobStatus};
+    use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+
+    /// Copy a sample into a fresh directory so tests don't overwrite the assets
+    fn scratch_copy(test_name: &str, sample: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("image_converter_{}", test_name));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(sample);
+        fs::copy(Path::new("assets/samples").join(sample), &path).unwrap();
+        path
+    }
 
     #[test]
     fn png_to_jpeg_roundtrip() {
-        let input = Path::new("assets/samples/flowey.png");
-        let output = Path::new("assets/samples/flowey.jpg");
-        convert(input, &ImageFormat::JPEG).unwrap();
-        let img = image::open(output).unwrap();
+        let input = scratch_copy("roundtrip", "flowey.png");
+        let output = input.with_extension("jpg");
+        let dimensions = image::image_dimensions(&input).unwrap();
+        convert(&input, &ImageFormat::JPEG).unwrap();
+        let img = image::open(&output).unwrap();
         assert_eq!(img.color(), image::ColorType::Rgb8);
-        convert(output, &ImageFormat::PNG).unwrap();
-        let img = image::open(output).unwrap();
-        assert_eq!(img.color(), image::ColorType::Rgba8); // No complicated tests, just check if the image is not corrupted 
+        fs::remove_file(&input).unwrap();
+        convert(&output, &ImageFormat::PNG).unwrap();
+        let img = image::open(&input).unwrap();
+        assert_eq!(img.color(), image::ColorType::Rgb8); // No complicated tests, just check if the image is not corrupted 
+        assert_eq!(img.dimensions(), dimensions);
+    }
+
+    #[test]
+    fn queue_runs_jobs_in_order() {
+        let input = scratch_copy("queue", "algebra.png");
+        let mut queue = ConversionQueue::default();
+        queue.push(input.clone(), ImageFormat::BMP, ConversionOptions::default());
+        queue.push(input.with_extension("missing.png"), ImageFormat::GIF, ConversionOptions::default());
+        queue.push(input.clone(), ImageFormat::JPEG, ConversionOptions::default());
+        queue.shift_down();
+        assert_eq!(queue.jobs[1].kind, JobKind::Convert(ImageFormat::BMP));
+
+        assert!(queue.start());
+        while !queue.step() {}
+
+        assert_eq!(queue.count(&JobStatus::Done), 2);
+        assert_eq!(queue.count(&JobStatus::Failed(String::new())), 1);
+        let bmp_job = &queue.jobs[1];
+        let bmp_output = bmp_job.output_path.as_ref().unwrap();
+        assert!(bmp_output.exists());
+        assert_eq!(bmp_job.bytes_after, Some(fs::metadata(bmp_output).unwrap().len()));
+
+        queue.selected_index = 0;
+        assert!(queue.retry_selected());
+        assert_eq!(queue.jobs[0].status, JobStatus::Pending);
+    }
+
+    #[test]
+    fn marked_files_are_queued_together() {
+        let input = scratch_copy("marks", "algebra.png");
+        let dir = input.parent().unwrap().to_path_buf();
+        fs::copy("assets/samples/algebra.bmp", dir.join("algebra.bmp")).unwrap();
+        fs::copy("assets/samples/flowey.jpg", dir.join("flowey.jpg")).unwrap();
+        fs::create_dir(dir.join("nested")).unwrap();
+
+        let mut app = AppState::new(dir.clone());
+        let press = |app: &mut AppState, c: char| {
+            handle_input(app, KeyEvent::new(KeyCode::Char(c), KeyModifiers::NONE));
+        };
+
+        // A key with no binding doesn't swallow the next one
+        press(&mut app, 'x');
+        press(&mut app, 'a');
+        assert_eq!(app.marked.len(), 3);
+        press(&mut app, '*');
+        assert!(app.marked.is_empty());
+
+        // Rows: "..", "nested/", "algebra.bmp", "algebra.png", "flowey.jpg"
+        app.selected_index = 1;
+        press(&mut app, 'v');
+        app.move_down();
+        app.move_down();
+        press(&mut app, 'v');
+        assert_eq!(app.marked.len(), 2);
+        press(&mut app, ' ');
+        assert!(!app.marked.contains(&input));
+
+        app.selected_format_index = 3;
+        app.confirm_conversion();
+        assert!(app.marked.is_empty());
+        assert_eq!(app.queue.jobs.len(), 1);
+        assert_eq!(app.queue.jobs[0].input_path, dir.join("algebra.bmp"));
+        assert_eq!(app.queue.jobs[0].kind, JobKind::Convert(ImageFormat::GIF));
+    }
+
+    #[test]
+    fn convert_mode_previews_source_and_output() {
+        let input = scratch_copy("preview", "algebra.png");
+        let mut app = AppState::new(input.parent().unwrap().to_path_buf());
+        app.selected_file = Some(input);
+        app.mode = AppMode::ConvertMode;
+        app.selected_format_index = 1;
+
+        let mut terminal = Terminal::new(TestBackend::new(100, 40)).unwrap();
+        terminal.draw(|f| draw(f, &mut app)).unwrap();
+
+        let rendered: String = terminal.backend().buffer().content().iter().map(|cell| cell.symbol()).collect();
+        assert!(rendered.contains("As JPEG"));
+        assert!(rendered.contains('▀'), "half-block preview was not rendered");
+        assert!(!rendered.contains("Preview unavailable"));
+
+        // The preview skips the size search and says so
+        app.options.max_bytes = Some(4096);
+        terminal.draw(|f| draw(f, &mut app)).unwrap();
+        let rendered: String = terminal.backend().buffer().content().iter().map(|cell| cell.symbol()).collect();
+        assert!(rendered.contains("As JPEG (approximate)"));
+        assert!(!rendered.contains("Preview unavailable"));
+    }
+
+    #[test]
+    fn inspect_reports_dimensions_and_alpha() {
+        let info = converter::inspect(Path::new("assets/samples/flowey.webp")).unwrap();
+        assert_eq!(info.format, "WEBP");
+        assert_eq!((info.width, info.height), (400, 301));
+        assert_eq!(info.bit_depth, 8);
+        assert_eq!(info.frame_count, 1);
+        assert!(info.alpha_used);
+
+        let info = converter::inspect(Path::new("assets/samples/algebra.jpg")).unwrap();
+        assert!(!info.has_alpha_channel && !info.alpha_used);
+        let json = serde_json::to_value(&info).unwrap();
+        assert_eq!(json["format"], "JPEG");
+        assert_eq!(json["file_size"], info.file_size);
+    }
+
+    #[test]
+    fn compare_measures_lossy_damage() {
+        let png = Path::new("assets/samples/algebra.png");
+        let jpg = Path::new("assets/samples/algebra.jpg");
+
+        let identical = converter::compare::compare(png, png).unwrap();
+        assert!(identical.psnr.is_infinite());
+        assert_eq!(identical.ssim, 1.0);
+        assert_eq!(identical.savings(), 0.0);
+
+        let lossy = converter::compare::compare(png, jpg).unwrap();
+        assert!(lossy.psnr > 20.0 && lossy.psnr.is_finite());
+        assert!(lossy.ssim > 0.5 && lossy.ssim < 1.0);
+        assert!(lossy.savings() > 0.0);
+
+        let heatmap = converter::compare::difference_map(&image::open(png).unwrap(), &image::open(jpg).unwrap()).unwrap();
+        assert_eq!(heatmap.dimensions(), (550, 368));
+    }
+
+    #[test]
+    fn output_dir_template_and_collisions() {
+        let input = scratch_copy("naming", "algebra.png");
+        let out_dir = input.parent().unwrap().join("out");
+        let mut options = ConversionOptions {
+            output_dir: Some(out_dir.clone()),
+            name_template: Some("{stem}_{width}w-{quality}.{ext}".to_string()),
+            quality: Some(70),
+            ..ConversionOptions::default()
+        };
+
+        let report = convert_with_options(&input, &ImageFormat::JPEG, &options).unwrap();
+        assert_eq!(report.output_path, out_dir.join("algebra_550w-70.jpg"));
+        assert!(report.output_path.exists());
+
+        options.collision = CollisionPolicy::AutoSuffix;
+        let report = convert_with_options(&input, &ImageFormat::JPEG, &options).unwrap();
+        assert_eq!(report.output_path, out_dir.join("algebra_550w-70_1.jpg"));
+
+        options.collision = CollisionPolicy::Skip;
+        let report = convert_with_options(&input, &ImageFormat::JPEG, &options).unwrap();
+        assert!(report.skipped);
+
+        options.collision = CollisionPolicy::Fail;
+        let result = convert_with_options(&input, &ImageFormat::JPEG, &options);
+        assert!(matches!(result, Err(ConverterError::OutputExists(_))));
+
+        // The queue shows the path the job wrote, named after the downscaled width
+        let mut queue = ConversionQueue::default();
+        let downscaled = ConversionOptions { max_dimensions: Some((275, 275)), ..options.clone() };
+        queue.push(input.clone(), ImageFormat::JPEG, downscaled);
+        assert!(queue.start());
+        while !queue.step() {}
+        assert_eq!(queue.jobs[0].output_path, Some(out_dir.join("algebra_275w-70.jpg")));
+
+        // {quality} is the one a size search settled on, and needs some quality
+        options.collision = CollisionPolicy::Overwrite;
+        options.max_bytes = Some(20_000);
+        let report = convert_with_options(&input, &ImageFormat::JPEG, &options).unwrap();
+        let chosen = report.chosen_quality.unwrap();
+        assert_eq!(report.output_path, out_dir.join(format!("algebra_550w-{}.jpg", chosen)));
+        options.max_bytes = None;
+        options.quality = None;
+        let result = convert_with_options(&input, &ImageFormat::JPEG, &options);
+        assert!(matches!(result, Err(ConverterError::InvalidTemplate(_))));
+
+        options.name_template = Some("{stem}-{nope}.{ext}".to_string());
+        let result = convert_with_options(&input, &ImageFormat::JPEG, &options);
+        assert!(matches!(result, Err(ConverterError::InvalidTemplate(_))));
+    }
+
+    #[test]
+    fn atomic_write_replaces_without_leftovers() {
+        let input = scratch_copy("atomic", "algebra.png");
+        let dir = input.parent().unwrap();
+        let target = dir.join("algebra.bmp");
+        fs::write(&target, b"stale").unwrap();
+
+        let report = convert(&input, &ImageFormat::BMP).unwrap();
+        assert_eq!(report.output_path, target);
+        assert_eq!(image::open(&target).unwrap().width(), 550);
+
+        let missing_dir = dir.join("missing").join("out.png");
+        let result = converter::output::write_atomic(&missing_dir, b"data");
+        assert!(matches!(result, Err(ConverterError::Io { stage: Stage::Write, .. })));
+
+        let leftovers: Vec<_> = fs::read_dir(dir)
+            .unwrap()
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| entry.file_name().to_string_lossy().ends_with(".tmp"))
+            .collect();
+        assert!(leftovers.is_empty());
+    }
+
+    #[test]
+    fn in_place_backup_and_delete_original() {
+        let input = scratch_copy("in_place", "algebra.png");
+        let dir = input.parent().unwrap().to_path_buf();
+        let original = fs::read(&input).unwrap();
+
+        let options = ConversionOptions {
+            in_place: true,
+            backup: BackupPolicy::Suffix(".bak".to_string()),
+            ..ConversionOptions::default()
+        };
+        let report = convert_with_options(&input, &ImageFormat::PNG, &options).unwrap();
+        assert_eq!(report.output_path, input);
+        assert_eq!(report.backup_path, Some(dir.join("algebra.png.bak")));
+        assert_eq!(fs::read(dir.join("algebra.png.bak")).unwrap(), original);
+
+        let trash = dir.join("trash");
+        let mut options = ConversionOptions {
+            output_dir: Some(dir.join("same_format")),
+            backup: BackupPolicy::TrashDir(trash.clone()),
+            delete_original: true,
+            ..ConversionOptions::default()
+        };
+        // A conversion to the input's own format keeps the input
+        let report = convert_with_options(&input, &ImageFormat::PNG, &options).unwrap();
+        assert!(!report.original_removed);
+        assert!(input.exists() && report.output_path.exists());
+        assert!(!trash.exists());
+
+        options.output_dir = None;
+        let report = convert_with_options(&input, &ImageFormat::BMP, &options).unwrap();
+        assert!(report.original_removed);
+        assert!(!input.exists());
+        assert!(report.output_path.exists());
+        assert_eq!(fs::read(trash.join("algebra.png")).unwrap(), original);
+    }
+
+    #[test]
+    fn same_format_reencodes_only_with_options() {
+        let input = scratch_copy("reencode", "algebra.jpg");
+        let original = fs::read(&input).unwrap();
+        let mut options = ConversionOptions {
+            in_place: true,
+            ..ConversionOptions::default()
+        };
+
+        let report = convert_with_options(&input, &ImageFormat::JPEG, &options).unwrap();
+        assert_eq!(report.output_bytes, original.len() as u64);
+        assert_eq!(fs::read(&input).unwrap(), original);
+
+        options.quality = Some(20);
+        let report = convert_with_options(&input, &ImageFormat::JPEG, &options).unwrap();
+        assert!(report.output_bytes < original.len() as u64);
+        assert_eq!(image::open(&input).unwrap().width(), 550);
+    }
+
+    #[test]
+    fn png_optimizer_is_lossless() {
+        let input = scratch_copy("optimize", "algebra.png");
+        let original = image::open(&input).unwrap().to_rgba8();
+        let options = ConversionOptions {
+            output_dir: Some(input.parent().unwrap().join("out")),
+            png_optimization: Some(2),
+            ..ConversionOptions::default()
+        };
+
+        let report = convert_with_options(&input, &ImageFormat::PNG, &options).unwrap();
+        assert!(report.optimizer_savings.unwrap() > 0);
+        assert!(report.output_bytes < report.input_bytes);
+        assert_eq!(image::open(&report.output_path).unwrap().to_rgba8(), original);
+    }
+
+    #[test]
+    fn lossy_webp_follows_the_feature() {
+        let input = scratch_copy("lossy_webp", "algebra.jpg");
+        let options = ConversionOptions {
+            quality: Some(60),
+            webp: WebpOptions { mode: WebpMode::Lossy, ..WebpOptions::default() },
+            ..ConversionOptions::default()
+        };
+
+        let result = convert_with_options(&input, &ImageFormat::WEBP, &options);
+        if cfg!(feature = "lossy-webp") {
+            let lossy = result.unwrap();
+            let lossless = fs::metadata(Path::new("assets/samples/algebra.webp")).unwrap().len();
+            assert!(lossy.output_bytes < lossless);
+            assert_eq!(image::open(&lossy.output_path).unwrap().width(), 550);
+        } else {
+            assert!(matches!(result, Err(ConverterError::UnsupportedFormat(_))));
+        }
+    }
+
+    #[test]
+    fn progressive_jpeg_follows_the_feature() {
+        let input = scratch_copy("mozjpeg", "algebra.png");
+        let mut options = ConversionOptions {
+            quality: Some(80),
+            jpeg: JpegOptions {
+                progressive: true,
+                optimize_coding: true,
+                subsampling: ChromaSubsampling::Yuv444,
+            },
+            ..ConversionOptions::default()
+        };
+
+        let result = convert_with_options(&input, &ImageFormat::JPEG, &options);
+        if cfg!(feature = "mozjpeg") {
+            let full_chroma = result.unwrap();
+            let bytes = fs::read(&full_chroma.output_path).unwrap();
+            // SOF2 marks a progressive frame
+            assert!(bytes.windows(2).any(|marker| marker == [0xFF, 0xC2]));
+            assert_eq!(image::open(&full_chroma.output_path).unwrap().width(), 550);
+
+            options.jpeg.subsampling = ChromaSubsampling::Yuv420;
+            let half_chroma = convert_with_options(&input, &ImageFormat::JPEG, &options).unwrap();
+            assert!(half_chroma.output_bytes < full_chroma.output_bytes);
+        } else {
+            assert!(matches!(result, Err(ConverterError::UnsupportedFormat(_))));
+        }
+    }
+
+    #[test]
+    fn max_bytes_searches_quality_then_size() {
+        let input = scratch_copy("max_bytes", "algebra.png");
+        let mut options = ConversionOptions {
+            max_bytes: Some(20_000),
+            ..ConversionOptions::default()
+        };
+
+        let report = convert_with_options(&input, &ImageFormat::JPEG, &options).unwrap();
+        assert!(report.output_bytes <= 20_000);
+        assert!(report.chosen_quality.is_some());
+        assert_eq!(report.downscaled_to, None);
+
+        options.max_bytes = Some(2_000);
+        let result = convert_with_options(&input, &ImageFormat::JPEG, &options);
+        assert!(matches!(result, Err(ConverterError::TooLarge { limit: 2_000, .. })));
+
+        options.allow_downscale = true;
+        let report = convert_with_options(&input, &ImageFormat::JPEG, &options).unwrap();
+        assert!(report.output_bytes <= 2_000);
+        let (width, _) = report.downscaled_to.unwrap();
+        assert!(width < 550);
+        assert_eq!(image::open(&report.output_path).unwrap().width(), width);
+
+        // The options pane rejects sizes that don't fit in a byte count
+        OptionField::MaxBytes.apply_text(&mut options, "500K").unwrap();
+        assert_eq!(options.max_bytes, Some(500 * 1024));
+        assert!(OptionField::MaxBytes.apply_text(&mut options, "18446744073709551615K").is_err());
+        assert_eq!(options.max_bytes, Some(500 * 1024));
+    }
+
+    #[test]
+    fn min_ssim_picks_lowest_passing_quality() {
+        let input = scratch_copy("min_ssim", "algebra.png");
+        let source = image::open(&input).unwrap();
+        let options = ConversionOptions {
+            min_ssim: Some(0.95),
+            ..ConversionOptions::default()
+        };
+
+        let report = convert_with_options(&input, &ImageFormat::JPEG, &options).unwrap();
+        let quality = report.chosen_quality.unwrap();
+        assert!(report.achieved_ssim.unwrap() >= 0.95);
+        let output = image::open(&report.output_path).unwrap();
+        assert!(converter::compare::ssim(&source, &output).unwrap() >= 0.95);
+
+        // One step lower must miss the target, or the search would have picked it
+        if quality > 1 {
+            let lower = ConversionOptions { quality: Some(quality - 1), ..ConversionOptions::default() };
+            let bytes = converter::pipeline::encode(&source, &ImageFormat::JPEG, &lower).unwrap();
+            let decoded = image::load_from_memory(&bytes).unwrap();
+            assert!(converter::compare::ssim(&source, &decoded).unwrap() < 0.95);
+        }
+
+        let result = convert_with_options(&input, &ImageFormat::PNG, &options);
+        assert!(matches!(result, Err(ConverterError::UnsupportedFormat(_))));
+
+        // Both searches at once are refused before any encoding
+        let both = ConversionOptions { max_bytes: Some(1_000_000), ..options };
+        let error = convert_with_options(&input, &ImageFormat::JPEG, &both).unwrap_err();
+        assert_eq!(error.stage(), Some(Stage::Encode));
+    }
+
+    #[test]
+    fn errors_name_file_stage_and_cause() {
+        let input = scratch_copy("corrupt", "algebra.png");
+        let bytes = fs::read(&input).unwrap();
+        fs::write(&input, &bytes[..bytes.len() / 2]).unwrap();
+
+        let error = convert(&input, &ImageFormat::JPEG).unwrap_err();
+        assert!(error.is_corrupt_input());
+        assert!(!error.is_unsupported());
+        assert_eq!(error.stage(), Some(Stage::Decode));
+        assert_eq!(error.path(), Some(input.as_path()));
+        let source = std::error::Error::source(&error).unwrap();
+        assert!(source.downcast_ref::<image::ImageError>().is_some());
+
+        let renamed = input.with_extension("tga");
+        fs::rename(&input, &renamed).unwrap();
+        let error = convert(&renamed, &ImageFormat::JPEG).unwrap_err();
+        assert!(error.is_unsupported());
+
+        let error = convert(&input, &ImageFormat::JPEG).unwrap_err();
+        assert!(matches!(&error, ConverterError::Io { stage: Stage::Read, .. }));
+        assert!(error.to_string().contains("algebra.png"));
+    }
+
+    #[test]
+    fn converts_bytes_and_streams_without_files() {
+        let bmp = fs::read("assets/samples/algebra.bmp").unwrap();
+
+        let jpeg = converter::convert_bytes(&bmp, None, &ImageFormat::JPEG, &ConversionOptions::default()).unwrap();
+        assert_eq!(ImageFormat::detect(&jpeg), Some(ImageFormat::JPEG));
+
+        let options = ConversionOptions { quality: Some(40), ..ConversionOptions::default() };
+        let written = converter::convert_reader(
+            std::io::Cursor::new(&jpeg),
+            Vec::new(),
+            Some(ImageFormat::JPEG),
+            &ImageFormat::WEBP,
+            &options,
+        )
+        .unwrap();
+        assert_eq!(ImageFormat::detect(&written), Some(ImageFormat::WEBP));
+        assert_eq!(image::load_from_memory(&written).unwrap().width(), 550);
+
+        let result = converter::convert_bytes(b"not an image", None, &ImageFormat::PNG, &options);
+        assert!(result.unwrap_err().is_unsupported());
+    }
+
+    #[test]
+    fn conversions_keep_the_decoded_color_type() {
+        let jpeg = fs::read("assets/samples/algebra.jpg").unwrap();
+        let options = ConversionOptions::default();
+
+        // No alpha channel is invented for opaque sources
+        for target in [ImageFormat::PNG, ImageFormat::BMP, ImageFormat::WEBP] {
+            let bytes = converter::convert_bytes(&jpeg, None, &target, &options).unwrap();
+            let decoded = image::load_from_memory(&bytes).unwrap();
+            assert_eq!(decoded.color(), image::ColorType::Rgb8, "{:?}", target);
+        }
+
+        let png = fs::read("assets/samples/algebra.png").unwrap();
+        let source_color = image::load_from_memory(&png).unwrap().color();
+        let bytes = converter::png_converter::convert_png_to_webp(&png).unwrap();
+        assert_eq!(image::load_from_memory(&bytes).unwrap().color(), source_color);
+    }
+
+    #[test]
+    fn input_limits_reject_oversized_images() {
+        let input = scratch_copy("limits", "algebra.png");
+        let limited = |limits: InputLimits| ConversionOptions { limits, ..ConversionOptions::default() };
+
+        let options = limited(InputLimits { max_width: Some(100), ..InputLimits::default() });
+        match convert_with_options(&input, &ImageFormat::JPEG, &options) {
+            Err(ConverterError::LimitExceeded { path, kind: LimitKind::Width, max: 100 }) => {
+                assert_eq!(path.as_deref(), Some(input.as_path()));
+            }
+            other => panic!("expected a width limit error, got {:?}", other),
+        }
+
+        let png = fs::read(&input).unwrap();
+        let checks = [
+            (InputLimits { max_pixels: Some(1000), ..InputLimits::default() }, LimitKind::Pixels),
+            (InputLimits { max_input_bytes: Some(100), ..InputLimits::default() }, LimitKind::InputBytes),
+            (InputLimits { max_alloc: Some(1000), ..InputLimits::default() }, LimitKind::Allocation),
+        ];
+        for (limits, expected) in checks {
+            // Same-format passthrough is checked too
+            for target in [ImageFormat::PNG, ImageFormat::WEBP] {
+                match converter::convert_bytes(&png, None, &target, &limited(limits)) {
+                    Err(ConverterError::LimitExceeded { kind, .. }) => assert_eq!(kind, expected),
+                    other => panic!("expected a {} limit error, got {:?}", expected, other.map(|b| b.len())),
+                }
+            }
+        }
+
+        let generous = limited(InputLimits {
+            max_width: Some(4096),
+            max_pixels: Some(16_000_000),
+            max_frames: Some(1),
+            ..InputLimits::default()
+        });
+        let report = convert_with_options(&input, &ImageFormat::WEBP, &generous).unwrap();
+        assert!(report.output_path.exists());
+    }
+
+    #[test]
+    fn streaming_converts_row_by_row() {
+        let input = scratch_copy("streaming", "algebra.bmp");
+        let options = ConversionOptions { streaming: true, ..ConversionOptions::default() };
+
+        // Rows pass through unchanged when no resize is asked for
+        let report = convert_with_options(&input, &ImageFormat::PNG, &options).unwrap();
+        let expected = image::open(&input).unwrap().to_rgba8();
+        assert_eq!(image::open(&report.output_path).unwrap().to_rgba8(), expected);
+
+        let png = fs::read(&report.output_path).unwrap();
+        let bmp = converter::convert_reader(std::io::Cursor::new(&png), Vec::new(), None, &ImageFormat::BMP, &options)
+            .unwrap();
+        assert_eq!(image::load_from_memory(&bmp).unwrap().to_rgba8(), expected);
+
+        // Shrinking fits the box on both paths and keeps the aspect ratio
+        let fit = ConversionOptions { max_dimensions: Some((275, 1000)), ..options.clone() };
+        let shrunk = converter::convert_reader(std::io::Cursor::new(&png), Vec::new(), None, &ImageFormat::PNG, &fit)
+            .unwrap();
+        let in_memory = converter::convert_bytes(&png, None, &ImageFormat::PNG, &ConversionOptions {
+            streaming: false,
+            ..fit.clone()
+        })
+        .unwrap();
+        let shrunk = image::load_from_memory(&shrunk).unwrap();
+        let in_memory = image::load_from_memory(&in_memory).unwrap();
+        assert_eq!(shrunk.dimensions(), (275, 184));
+        assert_eq!(in_memory.dimensions(), (275, 184));
+        // The box filter and Lanczos should agree closely
+        assert!(converter::compare::ssim(&shrunk, &in_memory).unwrap() > 0.9);
+
+        // The shrink buffers count against the allocation limit as well as the rows do
+        let bmp = fs::read(&input).unwrap();
+        let tight = InputLimits { max_alloc: Some(4096), ..InputLimits::default() };
+        let stream = |options: &ConversionOptions| {
+            converter::convert_reader(std::io::Cursor::new(&bmp), Vec::new(), None, &ImageFormat::PNG, options)
+        };
+        assert!(stream(&ConversionOptions { limits: tight, ..options.clone() }).is_ok());
+        let result = stream(&ConversionOptions { limits: tight, ..fit.clone() });
+        assert!(matches!(result, Err(ConverterError::LimitExceeded { kind: LimitKind::Allocation, .. })));
+
+        let jpeg = scratch_copy("streaming_jpeg", "algebra.jpg");
+        let result = convert_with_options(&jpeg, &ImageFormat::PNG, &options);
+        assert!(result.unwrap_err().is_unsupported());
+    }
+
+    #[test]
+    fn streaming_checks_forged_headers_before_allocating() {
+        // Headers claiming rows of gigabytes, with no pixel data behind them
+        let mut bmp = vec![0u8; 54];
+        bmp[..2].copy_from_slice(b"BM");
+        bmp[10..14].copy_from_slice(&54u32.to_le_bytes());
+        bmp[14..18].copy_from_slice(&40u32.to_le_bytes());
+        bmp[18..22].copy_from_slice(&0x7fff_ffffu32.to_le_bytes());
+        bmp[22..26].copy_from_slice(&1u32.to_le_bytes());
+        bmp[26..28].copy_from_slice(&1u16.to_le_bytes());
+        bmp[28..30].copy_from_slice(&24u16.to_le_bytes());
+        let mut png = Vec::new();
+        drop(png::Encoder::new(&mut png, 1 << 30, 1).write_header().unwrap());
+
+        let streamed = |limits: InputLimits| ConversionOptions { streaming: true, limits, ..ConversionOptions::default() };
+        let checks = [
+            (InputLimits { max_width: Some(10_000), ..InputLimits::default() }, LimitKind::Width),
+            (InputLimits { max_alloc: Some(1 << 20), ..InputLimits::default() }, LimitKind::Allocation),
+        ];
+        for (forged, name) in [(&bmp, "forged.bmp"), (&png, "forged.png")] {
+            for (limits, expected) in checks {
+                let result = converter::convert_reader(
+                    std::io::Cursor::new(forged),
+                    Vec::new(),
+                    None,
+                    &ImageFormat::PNG,
+                    &streamed(limits),
+                );
+                match result {
+                    Err(ConverterError::LimitExceeded { kind, .. }) => assert_eq!(kind, expected),
+                    other => panic!("expected a {} limit error, got {:?}", expected, other.map(|b| b.len())),
+                }
+            }
+
+            // The file path reads the header for the output name first, and checks it there
+            let input = scratch_copy("streaming_forged", "flowey.png").with_file_name(name);
+            fs::write(&input, forged).unwrap();
+            let target = if name.ends_with(".bmp") { ImageFormat::PNG } else { ImageFormat::BMP };
+            match convert_with_options(&input, &target, &streamed(checks[0].0)) {
+                Err(ConverterError::LimitExceeded { kind: LimitKind::Width, .. }) => {}
+                other => panic!("expected a width limit error, got {:?}", other),
+            }
+        }
+    }
+
+    #[test]
+    fn converts_to_several_targets_at_once() {
+        let input = scratch_copy("many", "algebra.png");
+        let targets = [ImageFormat::PNG, ImageFormat::WEBP, ImageFormat::JPEG];
+        let options = ConversionOptions {
+            parallel: true,
+            collision: CollisionPolicy::AutoSuffix,
+            delete_original: true,
+            ..ConversionOptions::default()
+        };
+
+        let reports = converter::convert_many(&input, &targets, &options).unwrap();
+        for (report, target) in reports.iter().zip(targets) {
+            let report = report.as_ref().unwrap();
+            assert_eq!(ImageFormat::detect(&fs::read(&report.output_path).unwrap()), Some(target));
+            assert!(report.original_removed);
+        }
+        assert!(!input.exists());
+
+        // Same-format targets pass through, the others share one decode
+        let bmp = fs::read("assets/samples/algebra.bmp").unwrap();
+        let outputs = converter::convert_bytes_many(
+            &bmp,
+            None,
+            &[ImageFormat::BMP, ImageFormat::GIF],
+            &ConversionOptions::default(),
+        )
+        .unwrap();
+        assert_eq!(outputs[0].as_ref().unwrap(), &bmp);
+        assert_eq!(ImageFormat::detect(outputs[1].as_ref().unwrap()), Some(ImageFormat::GIF));
+
+        // The format list checks several targets, queued as one job that decodes once
+        let mut app = AppState::new(input.parent().unwrap().to_path_buf());
+        app.selected_file = Some(input.with_extension("webp"));
+        app.mode = AppMode::ConvertMode;
+        handle_input(&mut app, KeyEvent::new(KeyCode::Char(' '), KeyModifiers::NONE));
+        app.selected_format_index = 4;
+        handle_input(&mut app, KeyEvent::new(KeyCode::Char(' '), KeyModifiers::NONE));
+        handle_input(&mut app, KeyEvent::new(KeyCode::Enter, KeyModifiers::NONE));
+        let queued: Vec<_> = app.queue.jobs.iter().map(|job| job.kind.clone()).collect();
+        assert_eq!(queued, [JobKind::ConvertMany(vec![ImageFormat::PNG, ImageFormat::BMP])]);
+        assert!(app.queue.start());
+        while !app.queue.step() {}
+        let job = &app.queue.jobs[0];
+        assert_eq!(job.status, JobStatus::Done);
+        assert_eq!(job.generated_files, Some(2));
+        for (target, result) in &job.target_results {
+            let report = result.as_ref().unwrap();
+            assert_eq!(ImageFormat::detect(&fs::read(&report.output_path).unwrap()), Some(*target));
+        }
+        assert_eq!(job.output_path.as_ref(), Some(&job.target_results[0].1.as_ref().unwrap().output_path));
+    }
+
+    #[test]
+    fn responsive_sets_write_variants_snippet_and_manifest() {
+        let input = scratch_copy("responsive", "algebra.png");
+        let spec = ResponsiveSpec { widths: vec![200, 100, 4000], ..ResponsiveSpec::default() };
+
+        let set = responsive::generate(&input, &spec, &ConversionOptions::default()).unwrap();
+        // The oversized width is capped at the source's 550
+        let widths: Vec<_> = set.variants.iter().map(|variant| (variant.width, variant.format.as_str())).collect();
+        assert_eq!(widths, [
+            (100, "image/webp"), (100, "image/jpeg"),
+            (200, "image/webp"), (200, "image/jpeg"),
+            (550, "image/webp"), (550, "image/jpeg"),
+        ]);
+        for variant in &set.variants {
+            assert_eq!(fs::metadata(&variant.path).unwrap().len(), variant.bytes);
+        }
+        let webp_srcset = "algebra-100w.webp 100w, algebra-200w.webp 200w, algebra-550w.webp 550w";
+        assert!(set.html.contains(&format!(r#"<source type="image/webp" srcset="{}""#, webp_srcset)));
+        assert!(set.html.contains(r#"<img src="algebra-550w.jpg""#));
+        assert_eq!(fs::read_to_string(&set.html_path).unwrap(), set.html);
+        let manifest: serde_json::Value = serde_json::from_slice(&fs::read(&set.manifest_path).unwrap()).unwrap();
+        assert_eq!(manifest.as_array().unwrap().len(), 6);
+
+        // A template without {width} would write every width to one file
+        let flat = ConversionOptions { name_template: Some("{stem}-small.{ext}".to_string()), ..ConversionOptions::default() };
+        let result = responsive::generate(&input, &spec, &flat);
+        assert!(matches!(result, Err(ConverterError::InvalidTemplate(_))));
+
+        // The same job runs from the TUI queue
+        let mut app = AppState::new(input.parent().unwrap().to_path_buf());
+        app.selected_file = Some(input.clone());
+        app.mode = AppMode::ConvertMode;
+        handle_input(&mut app, KeyEvent::new(KeyCode::Char('w'), KeyModifiers::NONE));
+        assert!(app.queue.start());
+        while !app.queue.step() {}
+        assert_eq!(app.queue.jobs[0].status, JobStatus::Done);
+        assert_eq!(app.queue.jobs[0].output_path, Some(set.html_path));
+    }
+
+    #[test]
+    fn contact_sheets_grid_thumbnails_with_captions() {
+        let input = scratch_copy("thumbnails", "algebra.png");
+        let dir = input.parent().unwrap().to_path_buf();
+        fs::copy("assets/samples/flowey.png", dir.join("flowey.png")).unwrap();
+        fs::copy("assets/samples/algebra.bmp", dir.join("algebra.bmp")).unwrap();
+        fs::write(dir.join("notes.txt"), "not an image").unwrap();
+        let spec = ThumbnailSpec { size: (64, 64), columns: 2, ..ThumbnailSpec::default() };
+
+        let report = thumbnails::generate(&dir, &spec, &ConversionOptions::default()).unwrap();
+        assert!(report.failed.is_empty());
+        let names: Vec<_> = report.thumbnails.iter().map(|path| path.file_name().unwrap().to_owned()).collect();
+        // Sources sharing a stem keep their extension in the thumbnail name
+        assert_eq!(names, ["algebra-bmp-thumb.jpg", "algebra-png-thumb.jpg", "flowey-thumb.jpg"]);
+        for thumbnail in &report.thumbnails {
+            let (width, height) = image::image_dimensions(thumbnail).unwrap();
+            assert!(width <= 64 && height <= 64 && (width == 64 || height == 64));
+        }
+
+        // Two columns, two rows, each cell 64 high plus a 12 pixel caption, 8 pixels apart
+        assert_eq!(report.sheet_path, dir.join("thumbnails/contact-sheet.png"));
+        let sheet = image::open(&report.sheet_path).unwrap().to_rgba8();
+        assert_eq!(sheet.dimensions(), (2 * 64 + 3 * 8, 2 * (64 + 12) + 3 * 8));
+        let caption_row = 8 + 64 + 4..8 + 64 + 12;
+        assert!(caption_row.flat_map(|y| (8..72).map(move |x| (x, y))).any(|(x, y)| sheet[(x, y)].0 != [255; 4]));
+        assert_eq!(sheet[(0, 0)].0, [255; 4]);
+
+        // The same job runs from the TUI queue
+        let mut app = AppState::new(dir.clone());
+        handle_input(&mut app, KeyEvent::new(KeyCode::Char('t'), KeyModifiers::NONE));
+        assert!(app.queue.is_empty());
+        handle_input(&mut app, KeyEvent::new(KeyCode::Char('T'), KeyModifiers::SHIFT));
+        assert!(matches!(app.queue.jobs[0].kind, JobKind::ContactSheet(_)));
+        assert_eq!(app.queue.jobs[0].output_path, None);
+        assert!(app.queue.start());
+        while !app.queue.step() {}
+        assert_eq!(app.queue.jobs[0].status, JobStatus::Done);
+        assert_eq!(app.queue.jobs[0].output_path, Some(report.sheet_path));
+    }
+
+    #[test]
+    fn atlases_pack_trimmed_rotated_frames_and_unpack_them() {
+        let dir = scratch_copy("atlas", "flowey.png").parent().unwrap().to_path_buf();
+        // A tall sprite with a transparent border, a wide one and an opaque square
+        let mut tall = image::RgbaImage::new(20, 40);
+        for (x, y, pixel) in tall.enumerate_pixels_mut() {
+            if (4..14).contains(&x) && (6..36).contains(&y) {
+                *pixel = image::Rgba([x as u8 * 10, y as u8 * 5, 200, 255]);
+            }
+        }
+        let wide = image::RgbaImage::from_fn(30, 10, |x, y| image::Rgba([x as u8, 100, y as u8, 128]));
+        let square = image::RgbaImage::from_pixel(16, 16, image::Rgba([255, 0, 0, 255]));
+        let sprites = [("tall.png", &tall), ("wide.png", &wide), ("square.png", &square)];
+        let mut inputs = Vec::new();
+        for (name, img) in sprites {
+            img.save(dir.join(name)).unwrap();
+            inputs.push(dir.join(name));
+        }
+
+        let spec = AtlasSpec { padding: 1, power_of_two: true, rotation: true, ..AtlasSpec::default() };
+        let packed = atlas::pack(&inputs, &dir.join("sheet.png"), &spec, &ConversionOptions::default()).unwrap();
+        let size = packed.descriptor.meta.size;
+        assert!(size.w.is_power_of_two() && size.h.is_power_of_two());
+        assert_eq!(image::image_dimensions(&packed.image_path).unwrap(), (size.w, size.h));
+        let frame = &packed.descriptor.frames["tall.png"];
+        assert!(frame.rotated && frame.trimmed);
+        assert_eq!((frame.frame.w, frame.frame.h), (10, 30));
+        assert_eq!((frame.sprite_source_size.x, frame.sprite_source_size.y), (4, 6));
+        assert!(!packed.descriptor.frames["square.png"].trimmed);
+        // The tallest frame opens the first row
+        let first = &packed.descriptor.frames["square.png"].frame;
+        assert_eq!((first.x, first.y), (1, 1));
+        let json: serde_json::Value = serde_json::from_slice(&fs::read(&packed.descriptor_path).unwrap()).unwrap();
+        assert_eq!(json["frames"]["tall.png"]["sourceSize"], serde_json::json!({"w": 20, "h": 40}));
+        assert_eq!(json["meta"]["image"], "sheet.png");
+
+        // Growing the atlas width stops before it overflows
+        let unbounded = AtlasSpec { max_size: u32::MAX, ..spec };
+        atlas::pack(&inputs, &dir.join("unbounded.png"), &unbounded, &ConversionOptions::default()).unwrap();
+
+        // Unpacking restores every sprite exactly
+        let frames = atlas::unpack(&packed.descriptor_path, &dir.join("frames"), &ConversionOptions::default()).unwrap();
+        assert_eq!(frames.len(), 3);
+        for (name, img) in sprites {
+            assert_eq!(&image::open(dir.join("frames").join(name)).unwrap().to_rgba8(), img);
+        }
+
+        // The descriptor can't point the image outside its own directory
+        let mut escaping = json.clone();
+        escaping["meta"]["image"] = dir.join("sheet.png").to_str().unwrap().into();
+        let escaping_path = dir.join("escaping.json");
+        fs::write(&escaping_path, serde_json::to_vec(&escaping).unwrap()).unwrap();
+        assert!(atlas::unpack(&escaping_path, &dir.join("frames"), &ConversionOptions::default()).is_err());
+
+        // A grid sheet skips its empty cells
+        let mut grid = image::RgbaImage::new(16, 16);
+        image::imageops::replace(&mut grid, &square.view(0, 0, 8, 8).to_image(), 0, 0);
+        image::imageops::replace(&mut grid, &square.view(0, 0, 8, 8).to_image(), 8, 0);
+        image::imageops::replace(&mut grid, &square.view(0, 0, 8, 8).to_image(), 0, 8);
+        grid.save(dir.join("grid.png")).unwrap();
+        let cells = atlas::slice_grid(&dir.join("grid.png"), (8, 8), &dir.join("cells"), &ConversionOptions::default())
+            .unwrap();
+        let names: Vec<_> = cells.iter().map(|path| path.file_name().unwrap().to_owned()).collect();
+        assert_eq!(names, ["grid_0.png", "grid_1.png", "grid_2.png"]);
+    }
+
+    #[test]
+    fn watermarks_are_composited_before_encoding() {
+        let input = scratch_copy("watermark", "algebra.png");
+        let source = image::open(&input).unwrap().to_rgba8();
+        let mark = Watermark {
+            anchor: Anchor::TopLeft,
+            margin: 10,
+            opacity: 1.0,
+            scale: 0.2,
+            text_color: [255, 0, 0],
+            ..Watermark::new(WatermarkContent::Text("SAMPLE".to_string()))
+        };
+        let options = ConversionOptions {
+            watermark: Some(mark),
+            output_dir: Some(input.parent().unwrap().join("marked")),
+            ..ConversionOptions::default()
+        };
+
+        // Same-format output is re-encoded so the mark isn't skipped
+        let output = convert_with_options(&input, &ImageFormat::PNG, &options).unwrap().output_path;
+        let marked = image::open(&output).unwrap();
+        assert_eq!(marked.color(), image::open(&input).unwrap().color());
+        let marked = marked.to_rgba8();
+        // 550 * 0.2 = 110 pixels, so the 48 pixel wide text is drawn at twice its size
+        let inside = |x: u32, y: u32| (10..10 + 96).contains(&x) && (10..10 + 16).contains(&y);
+        assert!((0..550).flat_map(|x| (0..368).map(move |y| (x, y))).all(|(x, y)| {
+            inside(x, y) || marked[(x, y)] == source[(x, y)]
+        }));
+        assert!((10..106).any(|x| marked[(x, 12)].0 == [255, 0, 0, 255]));
+
+        // A tiled logo at partial opacity touches the whole image
+        let logo = ConversionOptions {
+            watermark: Some(Watermark {
+                tile: true,
+                opacity: 0.5,
+                ..Watermark::new(WatermarkContent::Image(
+                    Logo::load(Path::new("assets/samples/flowey.png"), &InputLimits::default()).unwrap(),
+                ))
+            }),
+            output_dir: Some(input.parent().unwrap().join("tiled")),
+            ..ConversionOptions::default()
+        };
+        let tiled = convert_with_options(&input, &ImageFormat::PNG, &logo).unwrap().output_path;
+        let tiled = image::open(&tiled).unwrap().to_rgba8();
+        let changed_rows = (0..368).filter(|&y| (0..550).any(|x| tiled[(x, y)] != source[(x, y)])).count();
+        assert!(changed_rows > 300);
+
+        // The logo is held to the input limits like any other image
+        let small = InputLimits { max_width: Some(100), ..InputLimits::default() };
+        let error = Logo::load(Path::new("assets/samples/flowey.png"), &small).unwrap_err();
+        assert!(matches!(error, ConverterError::LimitExceeded { kind: LimitKind::Width, .. }));
+
+        // Streaming can't composite, so it refuses instead of dropping the mark
+        let streamed = ConversionOptions { streaming: true, ..options };
+        let error = convert_with_options(&input, &ImageFormat::BMP, &streamed).unwrap_err();
+        assert_eq!(error.stage(), Some(Stage::Transform));
+    }
+
+    #[test]
+    fn filters_run_in_order_after_resizing() {
+        let input = scratch_copy("filters", "algebra.png");
+        let source = image::open(&input).unwrap().to_rgb8();
+
+        // The options pane reads and shows the same filter list
+        let mut options = ConversionOptions {
+            output_dir: Some(input.parent().unwrap().join("filtered")),
+            ..ConversionOptions::default()
+        };
+        OptionField::Filters.apply_text(&mut options, "Grayscale, invert").unwrap();
+        assert_eq!(options.filters, [Filter::Grayscale, Filter::Invert]);
+        assert_eq!(OptionField::Filters.display(&options), "grayscale, invert");
+        assert!(OptionField::Filters.apply_text(&mut options, "blur 0").is_err());
+        assert!(filters::parse("sharpen 1.5 4, levels").is_err());
+        assert_eq!(filters::parse("sharpen 1.5 4").unwrap(), [Filter::Sharpen { sigma: 1.5, threshold: 4 }]);
+
+        let output = convert_with_options(&input, &ImageFormat::PNG, &options).unwrap().output_path;
+        let filtered = image::open(&output).unwrap().to_rgb8();
+        for (before, after) in source.pixels().zip(filtered.pixels()) {
+            let [r, g, b] = before.0.map(f32::from);
+            let gray = (0.2126 * r + 0.7152 * g + 0.0722 * b).round() as u8;
+            assert_eq!(after.0, [255 - gray; 3]);
+        }
+
+        // Order matters, and filters see the downscaled image
+        let run = |filters: Vec<Filter>| {
+            let options = ConversionOptions { filters, max_dimensions: Some((100, 100)), ..options.clone() };
+            let output = convert_with_options(&input, &ImageFormat::PNG, &options).unwrap().output_path;
+            image::open(output).unwrap().to_rgb8()
+        };
+        let brighten_first = run(vec![Filter::Brightness(60), Filter::Invert]);
+        let invert_first = run(vec![Filter::Invert, Filter::Brightness(60)]);
+        assert_eq!(brighten_first.dimensions(), (100, 67));
+        assert_ne!(brighten_first, invert_first);
+
+        // Auto-levels stretches a washed-out image to the full range
+        let flat = image::RgbImage::from_fn(64, 64, |x, _| image::Rgb([100 + x as u8 / 2, 120, 130]));
+        let stretched = filters::apply(&image::DynamicImage::ImageRgb8(flat), &[Filter::AutoLevels]).to_rgb8();
+        let reds: Vec<u8> = stretched.pixels().map(|pixel| pixel[0]).collect();
+        assert_eq!((reds.iter().min(), reds.iter().max()), (Some(&0), Some(&255)));
+    }
+
+    #[test]
+    fn filter_presets_are_stored_by_name() {
+        let dir = scratch_copy("presets", "algebra.png").parent().unwrap().to_path_buf();
+        let path = dir.join("config").join("filter_presets.json");
+        assert_eq!(FilterPresets::load(&path).unwrap(), FilterPresets::default());
+
+        let mut presets = FilterPresets::default();
+        presets.insert("faded", filters::parse("contrast 20, sepia").unwrap());
+        presets.insert("mono", vec![Filter::Grayscale]);
+        presets.save(&path).unwrap();
+        let loaded = FilterPresets::load(&path).unwrap();
+        assert_eq!(loaded, presets);
+        assert_eq!(loaded.get("faded").unwrap(), [Filter::Contrast(20.0), Filter::Sepia]);
+        let names: Vec<_> = loaded.iter().map(|(name, _)| name).collect();
+        assert_eq!(names, ["faded", "mono"]);
+
+        presets.remove("faded");
+        presets.save(&path).unwrap();
+        assert_eq!(FilterPresets::load(&path).unwrap().get("faded"), None);
+
+        // A chain that no longer parses names its preset
+        fs::write(&path, r#"{"broken": "levels"}"#).unwrap();
+        let error = FilterPresets::load(&path).unwrap_err();
+        assert!(error.to_string().contains("broken"));
     }
 }